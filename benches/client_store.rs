@@ -0,0 +1,223 @@
+//! Compares `ClientStore` with the single `Mutex<HashMap>` it replaced,
+//! under nickname lookups and broadcast fan-out from many concurrent tasks
+//! on a multi-threaded runtime.
+//!
+//!     cargo bench --bench client_store
+//!
+//! Each client's outbox is stood in for by a small locked queue, so the
+//! numbers measure the shared state rather than sockets.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::runtime::Runtime;
+
+#[allow(dead_code)]
+#[path = "../src/client_store.rs"]
+mod client_store;
+
+use client_store::{ClientStore, Nicknamed};
+
+/// Connected clients in every run
+const CLIENTS: u32 = 1_000;
+/// Lookups done by each task per iteration
+const LOOKUPS: usize = 1_000;
+/// Broadcasts sent by each task per iteration
+const BROADCASTS: usize = 10;
+/// Lines each stand-in outbox keeps before dropping the oldest
+const QUEUE_LINES: usize = 64;
+/// Concurrent tasks to compare
+const TASKS: [usize; 3] = [1, 4, 16];
+
+struct Client {
+    nickname: String,
+    queue: Mutex<VecDeque<Arc<str>>>,
+}
+
+impl Client {
+    fn new(id: u32) -> Self {
+        Self {
+            nickname: format!("Client{}", id),
+            queue: Mutex::new(VecDeque::with_capacity(QUEUE_LINES)),
+        }
+    }
+
+    /// Queue a line like `Outbox::push` does: lock, bound, append
+    fn push(&self, line: &Arc<str>) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() == QUEUE_LINES {
+            queue.pop_front();
+        }
+        queue.push_back(Arc::clone(line));
+    }
+}
+
+impl Nicknamed for Client {
+    fn nickname(&self) -> &str {
+        &self.nickname
+    }
+
+    fn set_nickname(&mut self, nickname: String) {
+        self.nickname = nickname;
+    }
+}
+
+/// The client map as it was before `ClientStore`
+type LockedMap = Arc<tokio::sync::Mutex<HashMap<u32, Client>>>;
+
+fn locked_map() -> LockedMap {
+    Arc::new(tokio::sync::Mutex::new(
+        (1..=CLIENTS).map(|id| (id, Client::new(id))).collect(),
+    ))
+}
+
+fn store() -> Arc<ClientStore<Client>> {
+    let store = ClientStore::new();
+    for id in 1..=CLIENTS {
+        store.insert(id, Client::new(id));
+    }
+    Arc::new(store)
+}
+
+/// Nickname of the client a task looks up on its `n`th lookup
+fn lookup_target(task: usize, n: usize) -> String {
+    let id = (task * 7_919 + n * 104_729) as u32 % CLIENTS + 1;
+    format!("client{}", id)
+}
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+/// Run `tasks` copies of `work` at once and time how long they all take
+fn run_tasks<F, Fut>(runtime: &Runtime, tasks: usize, iters: u64, work: F) -> Duration
+where
+    F: Fn(usize) -> Fut,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..iters {
+        let start = Instant::now();
+        runtime.block_on(async {
+            let handles: Vec<_> = (0..tasks).map(|task| tokio::spawn(work(task))).collect();
+            for handle in handles {
+                handle.await.unwrap();
+            }
+        });
+        elapsed += start.elapsed();
+    }
+    elapsed
+}
+
+fn lookups(c: &mut Criterion) {
+    let runtime = runtime();
+    let map = locked_map();
+    let store = store();
+    let mut group = c.benchmark_group("lookup");
+    for tasks in TASKS {
+        group.throughput(Throughput::Elements((tasks * LOOKUPS) as u64));
+
+        group.bench_with_input(BenchmarkId::new("mutex_map", tasks), &tasks, |b, &tasks| {
+            b.iter_custom(|iters| {
+                run_tasks(&runtime, tasks, iters, |task| {
+                    let map = Arc::clone(&map);
+                    async move {
+                        for n in 0..LOOKUPS {
+                            let nickname = lookup_target(task, n);
+                            let clients = map.lock().await;
+                            let found = clients
+                                .iter()
+                                .find(|(_, client)| client.nickname.eq_ignore_ascii_case(&nickname))
+                                .map(|(id, client)| (*id, client.queue.lock().unwrap().len()));
+                            std::hint::black_box(found);
+                        }
+                    }
+                })
+            })
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("client_store", tasks),
+            &tasks,
+            |b, &tasks| {
+                b.iter_custom(|iters| {
+                    run_tasks(&runtime, tasks, iters, |task| {
+                        let store = Arc::clone(&store);
+                        async move {
+                            for n in 0..LOOKUPS {
+                                let nickname = lookup_target(task, n);
+                                let found = store.find_by_nickname(&nickname).and_then(|id| {
+                                    store.get(id, |client| (id, client.queue.lock().unwrap().len()))
+                                });
+                                std::hint::black_box(found);
+                            }
+                        }
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn fan_out(c: &mut Criterion) {
+    let runtime = runtime();
+    let map = locked_map();
+    let store = store();
+    let line: Arc<str> = "Client1: hello everyone\n".into();
+    let mut group = c.benchmark_group("fan_out");
+    for tasks in TASKS {
+        group.throughput(Throughput::Elements(
+            (tasks * BROADCASTS) as u64 * CLIENTS as u64,
+        ));
+
+        group.bench_with_input(BenchmarkId::new("mutex_map", tasks), &tasks, |b, &tasks| {
+            b.iter_custom(|iters| {
+                run_tasks(&runtime, tasks, iters, |_| {
+                    let map = Arc::clone(&map);
+                    let line = Arc::clone(&line);
+                    async move {
+                        for _ in 0..BROADCASTS {
+                            for client in map.lock().await.values() {
+                                client.push(&line);
+                            }
+                        }
+                    }
+                })
+            })
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("client_store", tasks),
+            &tasks,
+            |b, &tasks| {
+                b.iter_custom(|iters| {
+                    run_tasks(&runtime, tasks, iters, |_| {
+                        let store = Arc::clone(&store);
+                        let line = Arc::clone(&line);
+                        async move {
+                            for _ in 0..BROADCASTS {
+                                store.for_each(|_, client| client.push(&line));
+                            }
+                        }
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default()
+        .sample_size(20)
+        .measurement_time(Duration::from_secs(3));
+    targets = lookups, fan_out
+}
+criterion_main!(benches);
@@ -0,0 +1,168 @@
+//! Load test for a running server: connects many clients, then measures
+//! broadcast fan-out and nickname lookups.
+//!
+//! Start the server without rate limits so only the shared state is measured:
+//!
+//!     ulimit -n 20000
+//!     cargo run --release -- --max-clients 0 --middlewares sanitize
+//!     cargo run --release --example load_test -- --clients 10000
+//!
+//! Options: --addr <addr> --clients <n> --senders <n> --messages <n> --lookups <n>
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+/// Received line counters shared by every client task
+#[derive(Default)]
+struct Counters {
+    broadcasts: AtomicUsize,
+    lookups: AtomicUsize,
+}
+
+struct Options {
+    addr: String,
+    clients: usize,
+    senders: usize,
+    messages: usize,
+    lookups: usize,
+}
+
+impl Options {
+    fn parse() -> Self {
+        let mut options = Options {
+            addr: "127.0.0.1:8080".to_string(),
+            clients: 10_000,
+            senders: 10,
+            messages: 10,
+            lookups: 2_000,
+        };
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        for pair in args.chunks(2) {
+            let value = pair.get(1).map(String::as_str).unwrap_or_default();
+            let number = || value.parse().expect("numeric option");
+            match pair[0].as_str() {
+                "--addr" => options.addr = value.to_string(),
+                "--clients" => options.clients = number(),
+                "--senders" => options.senders = number(),
+                "--messages" => options.messages = number(),
+                "--lookups" => options.lookups = number(),
+                other => panic!("Unknown option '{}'", other),
+            }
+        }
+        options
+    }
+}
+
+/// Connect one client, silence presence announcements and count the lines
+/// the benchmark cares about. Returns a sender for lines to write.
+async fn connect(
+    addr: &str,
+    counters: Arc<Counters>,
+) -> std::io::Result<mpsc::UnboundedSender<String>> {
+    let stream = TcpStream::connect(addr).await?;
+    let (reader, mut writer) = stream.into_split();
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+
+    tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            if writer.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if line.contains("bench-") {
+                counters.broadcasts.fetch_add(1, Ordering::Relaxed);
+            } else if line.starts_with("📋 Info for") || line.contains("not found") {
+                counters.lookups.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+
+    let _ = tx.send("/presence off\n".to_string());
+    Ok(tx)
+}
+
+/// Wait until `counter` reaches `target`, or give up after `timeout`
+async fn wait_for(counter: &AtomicUsize, target: usize, timeout: Duration) -> Option<Duration> {
+    let started = Instant::now();
+    while counter.load(Ordering::Relaxed) < target {
+        if started.elapsed() > timeout {
+            return None;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    Some(started.elapsed())
+}
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let options = Options::parse();
+    let counters = Arc::new(Counters::default());
+
+    let started = Instant::now();
+    let mut clients = Vec::with_capacity(options.clients);
+    for _ in 0..options.clients {
+        clients.push(connect(&options.addr, Arc::clone(&counters)).await?);
+    }
+    println!(
+        "Connected {} clients in {:.2?}",
+        options.clients,
+        started.elapsed()
+    );
+    // Let join announcements and /presence off settle
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    // Fan-out: every other client receives every message
+    let senders = options.senders.min(clients.len());
+    let sent = Instant::now();
+    for (sender, tx) in clients.iter().take(senders).enumerate() {
+        for message in 0..options.messages {
+            let _ = tx.send(format!("bench-{}-{}\n", sender, message));
+        }
+    }
+    let expected = senders * options.messages * (options.clients - 1);
+    match wait_for(&counters.broadcasts, expected, Duration::from_secs(120)).await {
+        Some(elapsed) => println!(
+            "Fan-out: {} lines delivered in {:.2?} ({:.0} lines/s)",
+            expected,
+            elapsed,
+            expected as f64 / elapsed.as_secs_f64()
+        ),
+        None => println!(
+            "Fan-out: timed out with {}/{} lines after {:.2?}",
+            counters.broadcasts.load(Ordering::Relaxed),
+            expected,
+            sent.elapsed()
+        ),
+    }
+
+    // Lookups: many clients resolve other clients by nickname at once
+    let lookups = options.lookups.min(clients.len());
+    for (index, tx) in clients.iter().take(lookups).enumerate() {
+        let target = (index * 7919) % options.clients + 1;
+        let _ = tx.send(format!("/info Client{}\n", target));
+    }
+    match wait_for(&counters.lookups, lookups, Duration::from_secs(120)).await {
+        Some(elapsed) => println!(
+            "Lookups: {} /info replies in {:.2?} ({:.0} lookups/s)",
+            lookups,
+            elapsed,
+            lookups as f64 / elapsed.as_secs_f64()
+        ),
+        None => println!(
+            "Lookups: timed out with {}/{} replies",
+            counters.lookups.load(Ordering::Relaxed),
+            lookups
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,498 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::server_message::{OutboundMessage, ServerMessage};
+use crate::shared_state::{ClientMap, Role};
+use crate::utils::duration::{format_duration, MAX_DURATION};
+
+/// What part of a message a rule's pattern is matched against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RuleScope {
+    /// The message text
+    #[default]
+    Message,
+    /// The sender's nickname
+    Nickname,
+}
+
+impl fmt::Display for RuleScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleScope::Message => write!(f, "message"),
+            RuleScope::Nickname => write!(f, "nickname"),
+        }
+    }
+}
+
+/// What happens when a rule matches
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub(crate) enum RuleAction {
+    /// Reject the message
+    Block,
+    /// Rewrite the matched text; `$1` style capture references are expanded
+    Replace { replacement: String },
+    /// Deliver the message but warn the sender
+    Warn,
+    /// Reject the message and mute the sender
+    Mute { minutes: u64 },
+    /// Reject the message and disconnect the sender
+    Kick,
+    /// Discard the message without telling the sender
+    Drop,
+    /// Let the message appear sent to the sender but deliver it to no one
+    Shadow,
+    /// Hold the message back and send it to the moderators instead
+    Review,
+}
+
+impl RuleAction {
+    /// Whether this action stops later rules from running
+    pub(crate) fn is_final(&self) -> bool {
+        !matches!(self, RuleAction::Replace { .. } | RuleAction::Warn)
+    }
+
+    /// What happens to the message, as shown by /automod test
+    pub(crate) fn outcome(&self) -> &'static str {
+        match self {
+            RuleAction::Block => "blocked",
+            RuleAction::Replace { .. } | RuleAction::Warn => "delivered",
+            RuleAction::Mute { .. } => "blocked and the sender muted",
+            RuleAction::Kick => "blocked and the sender kicked",
+            RuleAction::Drop => "dropped silently",
+            RuleAction::Shadow => "shown only to the sender",
+            RuleAction::Review => "held for moderator review",
+        }
+    }
+}
+
+impl fmt::Display for RuleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleAction::Block => write!(f, "block"),
+            RuleAction::Replace { replacement } => write!(f, "replace with '{}'", replacement),
+            RuleAction::Warn => write!(f, "warn"),
+            RuleAction::Mute { minutes } => write!(f, "mute for {}m", minutes),
+            RuleAction::Kick => write!(f, "kick"),
+            RuleAction::Drop => write!(f, "drop"),
+            RuleAction::Shadow => write!(f, "shadow"),
+            RuleAction::Review => write!(f, "review"),
+        }
+    }
+}
+
+/// How long a mute rule lasts, None if that is longer than `MAX_DURATION`
+pub(crate) fn mute_duration(minutes: u64) -> Option<Duration> {
+    minutes
+        .checked_mul(60)
+        .map(Duration::from_secs)
+        .filter(|duration| *duration <= MAX_DURATION)
+}
+
+/// A rule as written in the rules file
+#[derive(Debug, Deserialize)]
+struct RuleSpec {
+    name: String,
+    pattern: String,
+    #[serde(default)]
+    scope: RuleScope,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(flatten)]
+    action: RuleAction,
+}
+
+/// Layout of the rules file: a list of `[[rule]]` tables
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleSpec>,
+}
+
+/// A compiled auto-moderation rule
+#[derive(Debug)]
+pub(crate) struct Rule {
+    pub name: String,
+    regex: Regex,
+    pub scope: RuleScope,
+    pub action: RuleAction,
+    reason: Option<String>,
+}
+
+impl Rule {
+    /// Compile a rule, rejecting combinations that cannot be applied
+    fn compile(spec: RuleSpec) -> Result<Self, String> {
+        let regex = Regex::new(&spec.pattern)
+            .map_err(|e| format!("rule '{}': invalid pattern: {}", spec.name, e))?;
+        if spec.scope == RuleScope::Nickname && matches!(spec.action, RuleAction::Replace { .. }) {
+            return Err(format!(
+                "rule '{}': replace only works on the message scope",
+                spec.name
+            ));
+        }
+        if let RuleAction::Mute { minutes } = spec.action {
+            if minutes == 0 {
+                return Err(format!(
+                    "rule '{}': mute needs at least 1 minute",
+                    spec.name
+                ));
+            }
+            if mute_duration(minutes).is_none() {
+                return Err(format!(
+                    "rule '{}': mute can last at most {}",
+                    spec.name,
+                    format_duration(MAX_DURATION)
+                ));
+            }
+        }
+
+        Ok(Self {
+            name: spec.name,
+            regex,
+            scope: spec.scope,
+            action: spec.action,
+            reason: spec.reason,
+        })
+    }
+
+    /// The pattern as written in the rules file
+    pub(crate) fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Reason shown to the sender, defaulting to the rule name
+    pub(crate) fn reason(&self) -> &str {
+        self.reason.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// A rule that matched during an evaluation
+#[derive(Debug, Clone)]
+pub(crate) struct FiredRule {
+    pub name: String,
+    pub action: RuleAction,
+    pub reason: String,
+}
+
+/// Result of running the rules over one message
+#[derive(Debug)]
+pub(crate) struct Evaluation {
+    /// The message after any replace rules
+    pub message: String,
+    /// Every rule that matched, in order
+    pub fired: Vec<FiredRule>,
+}
+
+impl Evaluation {
+    /// The rule that stopped evaluation, if any
+    pub(crate) fn stopped_by(&self) -> Option<&FiredRule> {
+        self.fired.last().filter(|rule| rule.action.is_final())
+    }
+}
+
+/// Auto-moderation rules loaded from a TOML file, evaluated in file order
+#[derive(Debug)]
+pub(crate) struct AutomodRules {
+    path: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl AutomodRules {
+    /// Load rules from `path`. A missing file yields no rules.
+    pub(crate) fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let path = path.into();
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        let file: RulesFile = toml::from_str(&contents)
+            .map_err(|e| format!("Invalid automod rules {}: {}", path.display(), e))?;
+        let rules = file
+            .rules
+            .into_iter()
+            .map(Rule::compile)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid automod rules {}: {}", path.display(), e))?;
+
+        println!(
+            "Loaded {} automod rule(s) from {}",
+            rules.len(),
+            path.display()
+        );
+        Ok(Self { path, rules })
+    }
+
+    /// Load and compile rules from `path` on the blocking thread pool, so a
+    /// reload neither stalls the runtime nor holds the shared rules meanwhile
+    pub(crate) async fn load_in_background(path: PathBuf) -> Result<Self, String> {
+        tokio::task::spawn_blocking(move || Self::load(path))
+            .await
+            .map_err(|e| format!("Failed to load automod rules: {}", e))?
+    }
+
+    /// The file these rules were loaded from
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Run every rule over a message. Replace rules rewrite the text seen by
+    /// later rules; any action other than replace and warn stops evaluation.
+    pub(crate) fn evaluate(&self, nickname: &str, message: &str) -> Evaluation {
+        let mut message = message.to_string();
+        let mut fired = Vec::new();
+
+        for rule in &self.rules {
+            let text = match rule.scope {
+                RuleScope::Message => message.as_str(),
+                RuleScope::Nickname => nickname,
+            };
+            if !rule.regex.is_match(text) {
+                continue;
+            }
+
+            fired.push(FiredRule {
+                name: rule.name.clone(),
+                action: rule.action.clone(),
+                reason: rule.reason().to_string(),
+            });
+            if let RuleAction::Replace { replacement } = &rule.action {
+                message = rule
+                    .regex
+                    .replace_all(&message, replacement.as_str())
+                    .into_owned();
+            }
+            if rule.action.is_final() {
+                break;
+            }
+        }
+
+        Evaluation { message, fired }
+    }
+
+    /// The first nickname rule that forbids `nickname`, for refusing a
+    /// rename to it. Warn rules let the name through.
+    pub(crate) fn forbidding_nickname(&self, nickname: &str) -> Option<FiredRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.scope == RuleScope::Nickname && rule.action.is_final())
+            .find(|rule| rule.regex.is_match(nickname))
+            .map(|rule| FiredRule {
+                name: rule.name.clone(),
+                action: rule.action.clone(),
+                reason: rule.reason().to_string(),
+            })
+    }
+}
+
+pub(crate) type SharedAutomod = Arc<tokio::sync::Mutex<AutomodRules>>;
+
+/// Report an automatic moderation event on the server log and to every
+/// online moderator, tagged with the middleware that raised it
+pub(crate) async fn notify_moderators(clients: &ClientMap, source: &str, report: &str) {
+    println!("Moderation [{}]: {}", source, report);
+
+    let message = Arc::new(OutboundMessage::new(ServerMessage::Moderation {
+        source: source.to_string(),
+        text: report.to_string(),
+    }));
+    clients.for_each(|_, client_state| {
+        if client_state.role() >= Role::Moderator {
+            let _ = client_state.tx.push(Arc::clone(&message));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load rules from `contents`, through a file named after `name`
+    fn rules(name: &str, contents: &str) -> Result<AutomodRules, String> {
+        let path =
+            std::env::temp_dir().join(format!("automod-test-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let rules = AutomodRules::load(&path);
+        let _ = std::fs::remove_file(&path);
+        rules
+    }
+
+    fn fired(evaluation: &Evaluation) -> Vec<&str> {
+        evaluation
+            .fired
+            .iter()
+            .map(|rule| rule.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn replace_rules_chain_in_file_order() {
+        let rules = rules(
+            "chain",
+            r#"
+            [[rule]]
+            name = "cat"
+            pattern = "cat"
+            action = "replace"
+            replacement = "dog"
+
+            [[rule]]
+            name = "dog"
+            pattern = "(d)og"
+            action = "replace"
+            replacement = "${1}ingo"
+            "#,
+        )
+        .unwrap();
+        let evaluation = rules.evaluate("Alice", "my cat");
+        assert_eq!(evaluation.message, "my dingo");
+        assert_eq!(fired(&evaluation), ["cat", "dog"]);
+        assert!(evaluation.stopped_by().is_none());
+    }
+
+    #[test]
+    fn a_final_action_stops_evaluation() {
+        let rules = rules(
+            "final",
+            r#"
+            [[rule]]
+            name = "shout"
+            pattern = "[A-Z]{5}"
+            action = "warn"
+            reason = "Please don't shout"
+
+            [[rule]]
+            name = "spam"
+            pattern = "(?i)buy now"
+            action = "block"
+
+            [[rule]]
+            name = "never"
+            pattern = "."
+            action = "kick"
+            "#,
+        )
+        .unwrap();
+
+        let evaluation = rules.evaluate("Alice", "BUY NOW PLEASE");
+        assert_eq!(fired(&evaluation), ["shout", "spam"]);
+        assert_eq!(evaluation.fired[0].reason, "Please don't shout");
+        let stopped = evaluation.stopped_by().unwrap();
+        assert_eq!(stopped.name, "spam");
+        // Without a reason the rule name is shown
+        assert_eq!(stopped.reason, "spam");
+        assert_eq!(stopped.action, RuleAction::Block);
+    }
+
+    #[test]
+    fn warn_delivers_the_message_unchanged() {
+        let rules = rules(
+            "warn",
+            r#"
+            [[rule]]
+            name = "caps"
+            pattern = "^[A-Z ]+$"
+            action = "warn"
+            "#,
+        )
+        .unwrap();
+        let evaluation = rules.evaluate("Alice", "HELLO THERE");
+        assert_eq!(evaluation.message, "HELLO THERE");
+        assert_eq!(fired(&evaluation), ["caps"]);
+        assert!(evaluation.stopped_by().is_none());
+        assert!(rules.evaluate("Alice", "hello").fired.is_empty());
+    }
+
+    #[test]
+    fn nickname_rules_match_the_sender() {
+        let rules = rules(
+            "nickname",
+            r#"
+            [[rule]]
+            name = "impersonation"
+            pattern = "(?i)^admin"
+            scope = "nickname"
+            action = "block"
+
+            [[rule]]
+            name = "odd name"
+            pattern = "_$"
+            scope = "nickname"
+            action = "warn"
+            "#,
+        )
+        .unwrap();
+
+        let evaluation = rules.evaluate("Administrator", "hello");
+        assert_eq!(evaluation.stopped_by().unwrap().name, "impersonation");
+        assert!(rules.evaluate("Alice", "admin").fired.is_empty());
+
+        assert_eq!(
+            rules.forbidding_nickname("ADMIN").unwrap().name,
+            "impersonation"
+        );
+        // Warn rules let the name through
+        assert!(rules.forbidding_nickname("Alice_").is_none());
+        assert!(rules.forbidding_nickname("Alice").is_none());
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected_at_load() {
+        // Fields of a single rule named "bad"
+        let cases = [
+            (
+                "pattern",
+                r#"pattern = "(", action = "block""#,
+                "invalid pattern",
+            ),
+            (
+                "replace-nickname",
+                r#"pattern = "x", scope = "nickname", action = "replace", replacement = "y""#,
+                "replace only works on the message scope",
+            ),
+            (
+                "mute-zero",
+                r#"pattern = "x", action = "mute", minutes = 0"#,
+                "at least 1 minute",
+            ),
+            (
+                "mute-long",
+                r#"pattern = "x", action = "mute", minutes = 9999999999"#,
+                "mute can last at most",
+            ),
+            (
+                "action",
+                r#"pattern = "x", action = "explode""#,
+                "Invalid automod rules",
+            ),
+        ];
+        for (name, fields, expected) in cases {
+            let contents = format!(r#"rule = [{{ name = "bad", {} }}]"#, fields);
+            let error = rules(name, &contents).unwrap_err();
+            assert!(error.contains(expected), "{}: {}", name, error);
+        }
+
+        let error = rules("unknown-key", "rules = []\n").unwrap_err();
+        assert!(error.contains("Invalid automod rules"), "{}", error);
+    }
+
+    #[test]
+    fn missing_file_has_no_rules() {
+        let rules = AutomodRules::load("/nonexistent/automod.toml").unwrap();
+        assert!(rules.rules().is_empty());
+        assert!(rules.evaluate("Alice", "anything").fired.is_empty());
+    }
+}
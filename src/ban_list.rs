@@ -0,0 +1,489 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::utils::duration::{describe_sanction, expiry_after};
+
+/// An IP network in CIDR notation, e.g. `10.0.0.0/8`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Check if an address belongs to this network. IPv4-mapped IPv6
+    /// addresses are matched as the IPv4 address they carry.
+    pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = Self::mask_v4(self.prefix);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = Self::mask_v6(self.prefix);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    fn mask_v4(prefix: u8) -> u32 {
+        u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+    }
+
+    fn mask_v6(prefix: u8) -> u128 {
+        u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (network, prefix) = input
+            .split_once('/')
+            .ok_or_else(|| format!("Missing prefix length in '{}'", input))?;
+        let network: IpAddr = network
+            .parse()
+            .map_err(|_| format!("Invalid network address '{}'", network))?;
+        // A mapped network would never match, addresses are compared as IPv4
+        if network.to_canonical() != network {
+            return Err(format!("Use the IPv4 form of '{}'", network));
+        }
+        let prefix: u8 = prefix
+            .parse()
+            .map_err(|_| format!("Invalid prefix length '{}'", prefix))?;
+
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(format!("Prefix length {} exceeds {}", prefix, max));
+        }
+
+        // Store the network address with host bits cleared
+        let network = match network {
+            IpAddr::V4(v4) => IpAddr::from((u32::from(v4) & Self::mask_v4(prefix)).to_be_bytes()),
+            IpAddr::V6(v6) => IpAddr::from((u128::from(v6) & Self::mask_v6(prefix)).to_be_bytes()),
+        };
+
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// What a ban applies to
+#[derive(Debug, Clone)]
+pub(crate) enum BanTarget {
+    Nickname(String),
+    Ip(IpAddr),
+    Cidr(Cidr),
+}
+
+impl BanTarget {
+    /// Parse a ban target: an IP address, a CIDR range, or otherwise a nickname
+    pub(crate) fn parse(input: &str) -> Self {
+        let input = input.trim();
+        if let Ok(ip) = input.parse::<IpAddr>() {
+            BanTarget::Ip(ip.to_canonical())
+        } else if let Ok(cidr) = input.parse::<Cidr>() {
+            BanTarget::Cidr(cidr)
+        } else {
+            BanTarget::Nickname(input.to_string())
+        }
+    }
+
+    /// Check if this target covers the given address
+    pub(crate) fn matches_ip(&self, ip: IpAddr) -> bool {
+        match self {
+            BanTarget::Ip(banned) => *banned == ip.to_canonical(),
+            BanTarget::Cidr(cidr) => cidr.contains(ip),
+            BanTarget::Nickname(_) => false,
+        }
+    }
+
+    /// Check if this target covers the given nickname (case-insensitive)
+    pub(crate) fn matches_nickname(&self, nickname: &str) -> bool {
+        match self {
+            BanTarget::Nickname(banned) => banned.eq_ignore_ascii_case(nickname),
+            _ => false,
+        }
+    }
+
+    /// Check if two targets describe the same ban entry
+    fn same_as(&self, other: &BanTarget) -> bool {
+        match (self, other) {
+            (BanTarget::Nickname(a), BanTarget::Nickname(b)) => a.eq_ignore_ascii_case(b),
+            (BanTarget::Ip(a), BanTarget::Ip(b)) => a == b,
+            (BanTarget::Cidr(a), BanTarget::Cidr(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Serialize as `kind:value` for the ban file
+    fn to_record(&self) -> String {
+        match self {
+            BanTarget::Nickname(nickname) => format!("nick:{}", nickname),
+            BanTarget::Ip(ip) => format!("ip:{}", ip),
+            BanTarget::Cidr(cidr) => format!("cidr:{}", cidr),
+        }
+    }
+
+    /// Deserialize from `kind:value`
+    fn from_record(record: &str) -> Option<Self> {
+        let (kind, value) = record.split_once(':')?;
+        match kind {
+            "nick" if !value.is_empty() => Some(BanTarget::Nickname(value.to_string())),
+            "ip" => value
+                .parse::<IpAddr>()
+                .ok()
+                .map(|ip| BanTarget::Ip(ip.to_canonical())),
+            "cidr" => value.parse().ok().map(BanTarget::Cidr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanTarget::Nickname(nickname) => write!(f, "nickname '{}'", nickname),
+            BanTarget::Ip(ip) => write!(f, "IP {}", ip),
+            BanTarget::Cidr(cidr) => write!(f, "range {}", cidr),
+        }
+    }
+}
+
+/// A single ban entry
+#[derive(Debug, Clone)]
+pub(crate) struct Ban {
+    pub target: BanTarget,
+    pub expires_at: Option<SystemTime>,
+    pub reason: Option<String>,
+    /// Address a banned nickname was connected from, also refused so the
+    /// user cannot reconnect under a fresh nickname
+    pub last_ip: Option<IpAddr>,
+}
+
+impl Ban {
+    /// Create a ban, permanent when `duration` is None
+    pub(crate) fn new(
+        target: BanTarget,
+        duration: Option<Duration>,
+        reason: Option<String>,
+    ) -> Self {
+        Self {
+            target,
+            expires_at: duration.map(expiry_after),
+            reason,
+            last_ip: None,
+        }
+    }
+
+    /// Also refuse connections from the address the banned user was using
+    pub(crate) fn with_last_ip(mut self, ip: IpAddr) -> Self {
+        self.last_ip = Some(ip.to_canonical());
+        self
+    }
+
+    /// Check if this ban covers the given address
+    pub(crate) fn matches_ip(&self, ip: IpAddr) -> bool {
+        self.target.matches_ip(ip) || self.last_ip == Some(ip.to_canonical())
+    }
+
+    /// Check if the ban has run out
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= SystemTime::now())
+    }
+
+    /// Time left before the ban expires, None if permanent
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        self.expires_at.map(|expires_at| {
+            expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Describe reason and expiry for notices, e.g. ` (spam, expires in 2h)`
+    pub(crate) fn describe(&self) -> String {
+        describe_sanction(self.reason.as_deref(), self.remaining())
+    }
+
+    /// Serialize as a tab-separated line: `target  expires  reason  last_ip`
+    fn to_line(&self) -> String {
+        let expires = self
+            .expires_at
+            .and_then(|expires_at| expires_at.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs().to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let reason = self
+            .reason
+            .as_deref()
+            .unwrap_or("")
+            .replace(['\t', '\n', '\r'], " ");
+        let last_ip = self.last_ip.map(|ip| ip.to_string()).unwrap_or_default();
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.target.to_record(),
+            expires,
+            reason,
+            last_ip
+        )
+    }
+
+    /// Deserialize from a line written by `to_line`. The last_ip column is
+    /// optional so older ban files still load.
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(4, '\t');
+        let target = BanTarget::from_record(fields.next()?)?;
+        let expires_at = match fields.next()? {
+            "-" => None,
+            secs => Some(UNIX_EPOCH.checked_add(Duration::from_secs(secs.parse().ok()?))?),
+        };
+        let reason = fields
+            .next()
+            .map(str::trim)
+            .filter(|reason| !reason.is_empty())
+            .map(str::to_string);
+        let last_ip = match fields.next().map(str::trim) {
+            None | Some("") => None,
+            Some(ip) => Some(ip.parse::<IpAddr>().ok()?.to_canonical()),
+        };
+
+        Some(Ban {
+            target,
+            expires_at,
+            reason,
+            last_ip,
+        })
+    }
+}
+
+/// Ban list persisted to a plain text file, one ban per line
+///
+/// Changes return a snapshot of the file to write once the list's lock has
+/// been released, so disk I/O never holds up connections checking bans.
+pub(crate) struct BanList {
+    path: PathBuf,
+    bans: Vec<Ban>,
+    /// Bumped on every change, so an older snapshot never overwrites a newer one
+    generation: u64,
+    /// Generation of the last snapshot written to disk
+    written: Arc<Mutex<u64>>,
+}
+
+/// The contents of the ban file after a change, see `BanList`
+#[must_use = "the ban file is only updated once the snapshot is written"]
+pub(crate) struct BanFileSnapshot {
+    path: PathBuf,
+    contents: String,
+    generation: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl BanFileSnapshot {
+    /// Write the file on the blocking thread pool. Skipped if a newer
+    /// snapshot was written in the meantime.
+    pub(crate) async fn write(self) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || self.write_blocking())
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    fn write_blocking(self) -> io::Result<()> {
+        // Held for the whole write so two writers never share the temp file
+        let mut written = self
+            .written
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *written >= self.generation {
+            return Ok(());
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, self.contents)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        *written = self.generation;
+        Ok(())
+    }
+}
+
+impl BanList {
+    /// Load bans from `path`. A missing file yields an empty list.
+    pub(crate) fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut bans = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Ban::from_line(line) {
+                Some(ban) if !ban.is_expired() => bans.push(ban),
+                Some(_) => {}
+                None => eprintln!(
+                    "Ignoring malformed ban entry at {}:{}",
+                    path.display(),
+                    index + 1
+                ),
+            }
+        }
+
+        println!("Loaded {} ban(s) from {}", bans.len(), path.display());
+        Ok(Self {
+            path,
+            bans,
+            generation: 0,
+            written: Arc::new(Mutex::new(0)),
+        })
+    }
+
+    /// Drop expired entries and snapshot the list for writing to disk
+    fn snapshot(&mut self) -> BanFileSnapshot {
+        self.bans.retain(|ban| !ban.is_expired());
+        self.generation += 1;
+
+        let mut contents = String::from("# target\texpires (unix seconds or -)\treason\tlast ip\n");
+        for ban in &self.bans {
+            contents.push_str(&ban.to_line());
+            contents.push('\n');
+        }
+
+        BanFileSnapshot {
+            path: self.path.clone(),
+            contents,
+            generation: self.generation,
+            written: Arc::clone(&self.written),
+        }
+    }
+
+    /// Add a ban, replacing any existing ban on the same target
+    pub(crate) fn ban(&mut self, ban: Ban) -> BanFileSnapshot {
+        self.bans
+            .retain(|existing| !existing.target.same_as(&ban.target));
+        self.bans.push(ban);
+        self.snapshot()
+    }
+
+    /// Remove the ban on a target. Returns None if there was none.
+    pub(crate) fn unban(&mut self, target: &BanTarget) -> Option<BanFileSnapshot> {
+        let before = self.bans.len();
+        self.bans
+            .retain(|existing| !existing.target.same_as(target));
+        if self.bans.len() == before {
+            return None;
+        }
+        Some(self.snapshot())
+    }
+
+    /// Find an active ban covering the given address
+    pub(crate) fn find_ip(&self, ip: IpAddr) -> Option<&Ban> {
+        self.bans
+            .iter()
+            .find(|ban| !ban.is_expired() && ban.matches_ip(ip))
+    }
+
+    /// Find an active ban on the given nickname
+    pub(crate) fn find_nickname(&self, nickname: &str) -> Option<&Ban> {
+        self.bans
+            .iter()
+            .find(|ban| !ban.is_expired() && ban.target.matches_nickname(nickname))
+    }
+}
+
+pub(crate) type SharedBanList = Arc<tokio::sync::Mutex<BanList>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(input: &str) -> IpAddr {
+        input.parse().unwrap()
+    }
+
+    fn cidr(input: &str) -> Cidr {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn cidr_zero_prefix_matches_every_address_of_its_family() {
+        assert!(cidr("0.0.0.0/0").contains(ip("1.2.3.4")));
+        assert!(cidr("0.0.0.0/0").contains(ip("255.255.255.255")));
+        assert!(cidr("::/0").contains(ip("2001:db8::1")));
+        assert!(!cidr("0.0.0.0/0").contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn cidr_full_prefix_matches_one_address() {
+        assert!(cidr("10.1.2.3/32").contains(ip("10.1.2.3")));
+        assert!(!cidr("10.1.2.3/32").contains(ip("10.1.2.4")));
+        assert!(cidr("2001:db8::1/128").contains(ip("2001:db8::1")));
+        assert!(!cidr("2001:db8::1/128").contains(ip("2001:db8::2")));
+    }
+
+    #[test]
+    fn cidr_clears_host_bits_and_rejects_bad_prefixes() {
+        assert_eq!(cidr("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0.0".parse::<Cidr>().is_err());
+        assert!("::ffff:10.0.0.0/104".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn ipv4_mapped_addresses_match_ipv4_bans() {
+        let mapped = ip("::ffff:10.1.2.3");
+        assert!(cidr("10.0.0.0/8").contains(mapped));
+        assert!(BanTarget::parse("10.1.2.3").matches_ip(mapped));
+        assert!(BanTarget::parse("::ffff:10.1.2.3").matches_ip(ip("10.1.2.3")));
+
+        let ban = Ban::new(BanTarget::parse("someone"), None, None).with_last_ip(mapped);
+        assert!(ban.matches_ip(ip("10.1.2.3")));
+    }
+
+    #[test]
+    fn ban_lines_round_trip_and_reject_unrepresentable_expiry() {
+        let ban = Ban::new(
+            BanTarget::parse("10.0.0.0/8"),
+            Some(Duration::from_secs(60)),
+            Some("spam".to_string()),
+        );
+        let parsed = Ban::from_line(&ban.to_line()).unwrap();
+        assert!(parsed.target.same_as(&ban.target));
+        assert_eq!(parsed.reason.as_deref(), Some("spam"));
+        assert!(parsed.remaining().is_some());
+
+        assert!(Ban::from_line("ip:1.2.3.4\t18446744073709551615\t\t").is_none());
+    }
+
+    #[tokio::test]
+    async fn older_snapshot_never_overwrites_a_newer_one() {
+        let path = std::env::temp_dir().join(format!("bans-test-{}.txt", std::process::id()));
+        let mut bans = BanList::load(&path).unwrap();
+
+        let first = bans.ban(Ban::new(BanTarget::parse("1.2.3.4"), None, None));
+        let second = bans.ban(Ban::new(BanTarget::parse("5.6.7.8"), None, None));
+        second.write().await.unwrap();
+        first.write().await.unwrap();
+
+        let reloaded = BanList::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(reloaded.find_ip(ip("5.6.7.8")).is_some());
+        assert!(reloaded.find_ip(ip("1.2.3.4")).is_some());
+        assert_eq!(reloaded.bans.len(), 2);
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::server_message::{OutboundMessage, ServerMessage};
+use crate::shared_state::ClientMap;
+use crate::utils::error::ChatError;
+
+/// Channel every client starts in and returns to after /part
+pub(crate) const DEFAULT_CHANNEL: &str = "#general";
+/// Maximum channel name length in characters, including the leading '#'
+pub(crate) const MAX_CHANNEL_NAME_LENGTH: usize = 32;
+
+/// Validate a channel name and normalize it to lowercase, e.g. `#Rust` -> `#rust`
+pub(crate) fn parse_channel_name(input: &str) -> Result<String, ChatError> {
+    let name = input.trim();
+    let Some(rest) = name.strip_prefix('#') else {
+        return Err(ChatError::InvalidChannelName(
+            "channel names start with '#'".to_string(),
+        ));
+    };
+    if rest.is_empty() {
+        return Err(ChatError::InvalidChannelName(
+            "channel name cannot be empty".to_string(),
+        ));
+    }
+    if name.chars().count() > MAX_CHANNEL_NAME_LENGTH {
+        return Err(ChatError::InvalidChannelName(format!(
+            "max {} chars",
+            MAX_CHANNEL_NAME_LENGTH
+        )));
+    }
+    if !rest
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(ChatError::InvalidChannelName(
+            "only letters, numbers, '_' and '-' are allowed".to_string(),
+        ));
+    }
+    Ok(name.to_lowercase())
+}
+
+/// Count the members of every channel that currently has at least one
+pub(crate) fn member_counts(clients: &ClientMap) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    clients.for_each(|_, client_state| {
+        *counts.entry(client_state.channel.clone()).or_insert(0) += 1;
+    });
+    counts
+}
+
+/// Modes set on a channel by its operators
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChannelModes {
+    /// +i: only invited users may join
+    pub invite_only: bool,
+    /// +k: users must give this key to join
+    pub key: Option<String>,
+    /// +m: only operators and voiced users may speak
+    pub moderated: bool,
+    /// +t: only operators may change the topic
+    pub topic_locked: bool,
+}
+
+impl fmt::Display for ChannelModes {
+    /// Render as mode letters, e.g. `+ikm`. The key itself is never shown.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = [
+            (self.invite_only, 'i'),
+            (self.key.is_some(), 'k'),
+            (self.moderated, 'm'),
+            (self.topic_locked, 't'),
+        ];
+        let letters: String = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, letter)| *letter)
+            .collect();
+        if letters.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "+{}", letters)
+        }
+    }
+}
+
+/// Per-channel state: modes, topic and the clients holding channel privileges
+#[derive(Debug, Default)]
+pub(crate) struct Channel {
+    pub modes: ChannelModes,
+    pub topic: Option<String>,
+    operators: HashSet<u32>,
+    voiced: HashSet<u32>,
+    invited: HashSet<u32>,
+}
+
+impl Channel {
+    /// Check if the client is an operator of this channel
+    pub fn is_operator(&self, client_id: u32) -> bool {
+        self.operators.contains(&client_id)
+    }
+
+    /// Grant or revoke operator status. Returns true if state changed.
+    pub fn set_operator(&mut self, client_id: u32, operator: bool) -> bool {
+        if operator {
+            self.operators.insert(client_id)
+        } else {
+            self.operators.remove(&client_id)
+        }
+    }
+
+    /// Check if the client has been given a voice
+    pub fn is_voiced(&self, client_id: u32) -> bool {
+        self.voiced.contains(&client_id)
+    }
+
+    /// Grant or revoke voice. Returns true if state changed.
+    pub fn set_voiced(&mut self, client_id: u32, voiced: bool) -> bool {
+        if voiced {
+            self.voiced.insert(client_id)
+        } else {
+            self.voiced.remove(&client_id)
+        }
+    }
+
+    /// Allow the client to join while the channel is invite-only
+    pub fn invite(&mut self, client_id: u32) {
+        self.invited.insert(client_id);
+    }
+
+    /// Check if the client may send messages, given the moderated mode
+    pub fn can_speak(&self, client_id: u32) -> bool {
+        !self.modes.moderated || self.is_operator(client_id) || self.is_voiced(client_id)
+    }
+}
+
+/// Registry of channels that currently have members
+#[derive(Debug)]
+pub(crate) struct Channels {
+    channels: HashMap<String, Channel>,
+}
+
+impl Channels {
+    /// Create a registry holding only the default channel
+    pub(crate) fn new() -> Self {
+        let mut channels = HashMap::new();
+        channels.insert(DEFAULT_CHANNEL.to_string(), Channel::default());
+        Self { channels }
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Channel> {
+        self.channels.get(name)
+    }
+
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        self.channels.get_mut(name)
+    }
+
+    /// Admit a client to a channel, creating it with the client as its
+    /// operator if it does not exist. `bypass` skips the invite and key
+    /// checks, e.g. for server moderators.
+    pub(crate) fn admit(
+        &mut self,
+        name: &str,
+        client_id: u32,
+        key: Option<&str>,
+        bypass: bool,
+    ) -> Result<(), ChatError> {
+        let Some(channel) = self.channels.get_mut(name) else {
+            let mut channel = Channel::default();
+            channel.set_operator(client_id, true);
+            self.channels.insert(name.to_string(), channel);
+            return Ok(());
+        };
+
+        if !bypass {
+            if channel.modes.invite_only && !channel.invited.contains(&client_id) {
+                return Err(ChatError::ChannelAccessDenied(format!(
+                    "{} is invite-only",
+                    name
+                )));
+            }
+            if channel.modes.key.is_some() && channel.modes.key.as_deref() != key {
+                return Err(ChatError::ChannelAccessDenied(format!(
+                    "{} requires the correct key",
+                    name
+                )));
+            }
+        }
+
+        channel.invited.remove(&client_id);
+        Ok(())
+    }
+
+    /// Drop a client's privileges in a channel it left. Channels other than
+    /// the default one are removed once empty, which resets their modes.
+    pub(crate) fn leave(&mut self, name: &str, client_id: u32, still_occupied: bool) {
+        if !still_occupied && name != DEFAULT_CHANNEL {
+            self.channels.remove(name);
+        } else if let Some(channel) = self.channels.get_mut(name) {
+            channel.operators.remove(&client_id);
+            channel.voiced.remove(&client_id);
+        }
+    }
+
+    /// Forget pending invites for a client that disconnected
+    pub(crate) fn forget_client(&mut self, client_id: u32) {
+        for channel in self.channels.values_mut() {
+            channel.invited.remove(&client_id);
+        }
+    }
+}
+
+pub(crate) type SharedChannels = Arc<tokio::sync::Mutex<Channels>>;
+
+/// Send a server announcement to every member of a channel
+pub(crate) async fn broadcast_to_channel(clients: &ClientMap, channel: &str, message: &str) {
+    let message = Arc::new(OutboundMessage::new(ServerMessage::system(message)));
+    clients.for_each(|_, client_state| {
+        if client_state.channel == channel {
+            let _ = client_state.tx.push(Arc::clone(&message));
+        }
+    });
+}
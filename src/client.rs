@@ -1,26 +1,56 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
+use crate::channels::DEFAULT_CHANNEL;
 use crate::commands::Commands;
-use crate::middlewares::{MessageContext, MiddlewareChain};
-use crate::shared_state::{ClientMap, SharedClientState};
+use crate::history::{HistoryEntry, SharedHistory};
+use crate::middlewares::{Delivery, MessageContext};
+use crate::mutes::Mute;
+use crate::outbox::{outbox, Outbox, OutboxReceiver};
+use crate::presence::{self, DisconnectReason, PresenceEvent};
+use crate::protocol::{Protocol, Request, RequestBody};
+use crate::server_message::{ErrorCode, OutboundMessage, ServerMessage};
+use crate::shared_state::{ClientControl, ServerState, SharedClientState};
+use crate::traits::middleware_trait::MiddlewareError;
+use crate::utils::error::ChatError;
+use crate::utils::framing::{Frame, LineReader};
+use crate::utils::sanitize::sanitize;
 
 pub(crate) struct Client {
     id: u32,
     nickname: String,
     socket: TcpStream,
-    clients: ClientMap,
+    addr: SocketAddr,
+    protocol: Protocol,
+    state: ServerState,
+    /// Dropped once the client is fully disconnected, used to await draining on shutdown
+    shutdown_complete: mpsc::Sender<()>,
 }
 
 impl Client {
-    pub(crate) fn new(id: u32, socket: TcpStream, clients: ClientMap) -> Self {
+    pub(crate) fn new(
+        id: u32,
+        socket: TcpStream,
+        addr: SocketAddr,
+        protocol: Protocol,
+        state: ServerState,
+        shutdown_complete: mpsc::Sender<()>,
+    ) -> Self {
         let nickname = format!("Client{}", id);
         Client {
             id,
             nickname,
             socket,
-            clients,
+            addr,
+            protocol,
+            state,
+            shutdown_complete,
         }
     }
 
@@ -29,115 +59,356 @@ impl Client {
             id,
             mut nickname,
             socket,
-            clients,
+            addr,
+            protocol,
+            state,
+            shutdown_complete,
         } = self;
 
-        let (tx, rx) = mpsc::channel::<String>(10);
+        let (tx, rx) = outbox(
+            state.config.outbound_queue_bytes,
+            state.config.outbound_overflow,
+            protocol.output_format(state.config.output_format),
+        );
+        let (control_tx, mut control_rx) = mpsc::channel::<ClientControl>(4);
 
-        Self::register_client(id, &nickname, &tx, &clients).await;
-        let (mut reader, writer) = socket.into_split();
-        Self::spawn_writer_task(rx, writer);
+        let mute =
+            Self::register_client(id, &mut nickname, &tx, control_tx, addr, protocol, &state).await;
+        let (reader, writer) = socket.into_split();
+        let writer_task = Self::spawn_writer_task(rx, writer);
+        // Programs can't read their id and nickname off the MOTD, so tell them
+        if protocol == Protocol::Json {
+            let welcome = ServerMessage::Welcome {
+                id,
+                nickname: nickname.clone(),
+                channel: DEFAULT_CHANNEL.to_string(),
+            };
+            let _ = tx.send(welcome).await;
+        }
+        Self::send_motd(&tx, &state).await;
+        if let Some(mute) = mute {
+            let notice = format!(
+                "⚠️  You are still muted{}. You cannot send messages.",
+                mute.describe()
+            );
+            let _ = tx.send(ServerMessage::system(notice)).await;
+        }
+        Self::replay_history(&tx, &state.history, DEFAULT_CHANNEL).await;
+        let joined = PresenceEvent::Joined {
+            nickname: nickname.clone(),
+        };
+        presence::announce(&state.clients, id, joined).await;
 
-        Self::message_loop(id, &mut nickname, &tx, &clients, &mut reader).await;
+        let mut lines = LineReader::new(reader, state.config.max_line_length);
+        let reason = Self::message_loop(
+            id,
+            &mut nickname,
+            &tx,
+            &state,
+            protocol,
+            &mut lines,
+            &mut control_rx,
+        )
+        .await;
+
+        Self::disconnect_client(id, reason, &state).await;
 
-        Self::disconnect_client(id, &nickname, &clients).await;
+        // Wait for queued messages to be flushed before reporting completion.
+        // A client that overflowed is not reading, so don't wait on its socket.
+        tx.close();
+        if reason == DisconnectReason::Overflow {
+            writer_task.abort();
+        }
+        let _ = writer_task.await;
+        drop(shutdown_complete);
     }
 
-    /// Register client in the shared ClientMap
+    /// Register client in the shared ClientMap, re-applying any mute on its
+    /// address. The default nickname gets underscores appended if another
+    /// client already took it. Returns the mute so the client can be told
+    /// about it.
     async fn register_client(
         id: u32,
-        nickname: &str,
-        tx: &mpsc::Sender<String>,
-        clients: &ClientMap,
-    ) {
-        let client_state = SharedClientState::new(nickname.to_string(), tx.clone());
-        clients.lock().await.insert(id, client_state);
+        nickname: &mut String,
+        tx: &Outbox,
+        control_tx: mpsc::Sender<ClientControl>,
+        addr: SocketAddr,
+        protocol: Protocol,
+        state: &ServerState,
+    ) -> Option<Mute> {
+        let client_state =
+            SharedClientState::new(nickname.to_string(), tx.clone(), control_tx, addr, protocol);
+        *nickname = state.clients.insert(id, client_state);
+
+        let mute = state.mutes.lock().await.find(addr.ip()).cloned();
+        if let Some(mute) = &mute {
+            println!("Re-applying mute to client {} from {}", id, addr);
+            state
+                .clients
+                .get_mut(id, |client_state| client_state.mute(mute.clone()));
+        }
+        mute
+    }
+
+    /// Send the message of the day, if one is configured
+    async fn send_motd(tx: &Outbox, state: &ServerState) {
+        if let Some(motd) = &state.config.motd {
+            let _ = tx
+                .send(ServerMessage::system(format!("📢 {}", motd.trim_end())))
+                .await;
+        }
+    }
+
+    /// Send the most recent public messages to a newly joined client
+    async fn replay_history(tx: &Outbox, history: &SharedHistory, channel: &str) {
+        let _ = history
+            .lock()
+            .await
+            .send_replay(tx, channel, ServerMessage::system)
+            .await;
     }
 
-    /// Spawn a task to write messages to the client
+    /// Spawn a task to write messages to the client, rendered in its output
+    /// format. Queued messages are flushed before the socket is shut down.
     fn spawn_writer_task(
-        mut rx: mpsc::Receiver<String>,
+        mut rx: OutboxReceiver,
         mut writer: tokio::net::tcp::OwnedWriteHalf,
-    ) {
+    ) -> JoinHandle<()> {
         tokio::spawn(async move {
             while let Some(message) = rx.recv().await {
                 if writer.write_all(message.as_bytes()).await.is_err() {
                     break;
                 }
             }
-        });
+            let _ = writer.shutdown().await;
+        })
     }
 
-    /// Main message reading loop
+    /// Main message reading loop, one iteration per newline-delimited line,
+    /// read as text or as a JSON request depending on the protocol.
+    /// Also listens for control signals such as kicks and server shutdown,
+    /// and returns why the client is leaving.
     async fn message_loop(
         id: u32,
         nickname: &mut String,
-        tx: &mpsc::Sender<String>,
-        clients: &ClientMap,
-        reader: &mut tokio::net::tcp::OwnedReadHalf,
-    ) {
-        let mut buffer = [0; 1024];
+        tx: &Outbox,
+        state: &ServerState,
+        protocol: Protocol,
+        lines: &mut LineReader<tokio::net::tcp::OwnedReadHalf>,
+        control_rx: &mut mpsc::Receiver<ClientControl>,
+    ) -> DisconnectReason {
+        let idle_timeout = state.config.idle_timeout;
+
         loop {
-            match reader.read(&mut buffer).await {
-                Ok(0) => break,
-                Ok(n) => {
-                    let message = String::from_utf8_lossy(&buffer[0..n])
-                        .trim_end()
-                        .to_string();
-
-                    if !Self::handle_message(id, nickname, tx, clients, &message).await {
-                        break; // Quit command received
+            tokio::select! {
+                frame = lines.next_frame() => match frame {
+                    Ok(None) => return DisconnectReason::Quit,
+                    Ok(Some(Frame::Line(line))) => {
+                        let keep_going = match protocol {
+                            Protocol::Text => {
+                                Self::handle_message(id, nickname, tx, state, &line).await
+                            }
+                            Protocol::Json => {
+                                Self::handle_request(id, nickname, tx, state, &line).await
+                            }
+                        };
+                        if !keep_going {
+                            return DisconnectReason::Quit; // Quit command received
+                        }
+                        // Sending never waits, so give writer tasks a turn
+                        // between the lines of a buffered burst
+                        tokio::task::yield_now().await;
+                    }
+                    Ok(Some(Frame::TooLong)) => {
+                        let error = ChatError::LineTooLong {
+                            max: lines.max_line_length(),
+                        };
+                        let _ = tx
+                            .send(ServerMessage::refused(
+                                ErrorCode::LineTooLong,
+                                error.to_string(),
+                            ))
+                            .await;
+                    }
+                    Err(e) => {
+                        eprintln!("Error reading from client {}: {}", id, e);
+                        return DisconnectReason::Error;
+                    }
+                },
+                Some(control) = control_rx.recv() => match control {
+                    ClientControl::Kick { reason } => {
+                        let notice = match reason {
+                            Some(reason) => {
+                                format!("👢 You have been kicked by a moderator: {}", reason)
+                            }
+                            None => "👢 You have been kicked by a moderator.".to_string(),
+                        };
+                        let _ = tx.send(ServerMessage::system(notice)).await;
+                        return DisconnectReason::Kicked;
                     }
+                    ClientControl::Disconnect { notice } => {
+                        let _ = tx.send(ServerMessage::system(notice)).await;
+                        return DisconnectReason::Kicked;
+                    }
+                    ClientControl::Shutdown => return DisconnectReason::Shutdown,
+                },
+                _ = tx.overflowed() => {
+                    println!(
+                        "Client {} ({}) could not keep up with its messages",
+                        id, nickname
+                    );
+                    return DisconnectReason::Overflow;
                 }
-                Err(e) => {
-                    eprintln!("Error reading from client {}: {}", id, e);
-                    break;
+                // Recreated every iteration, so any activity resets the timer
+                _ = Self::idle_timer(idle_timeout) => {
+                    let _ = tx
+                        .send(ServerMessage::system(format!(
+                            "⏱️  Disconnected after {}s of inactivity",
+                            idle_timeout
+                        )))
+                        .await;
+                    return DisconnectReason::Timeout;
                 }
             }
         }
     }
 
+    /// Resolve after `secs` seconds, or never when the idle timeout is disabled
+    async fn idle_timer(secs: u64) {
+        if secs == 0 {
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(Duration::from_secs(secs)).await;
+    }
+
     /// Handle a single message (command or chat message)
     /// Returns false if client should disconnect
     async fn handle_message(
         id: u32,
         nickname: &mut String,
-        tx: &mpsc::Sender<String>,
-        clients: &ClientMap,
+        tx: &Outbox,
+        state: &ServerState,
         message: &str,
     ) -> bool {
         if message.starts_with('/') {
-            Self::handle_command(id, nickname, tx, clients, message).await
+            Self::handle_command(id, nickname, tx, state, message).await
         } else {
-            let mut ctx = MessageContext {
-                message: message.to_string(),
-                sender_id: id,
-                nickname: nickname.clone(),
-                clients: clients.clone(),
-            };
+            Self::handle_chat(id, nickname, tx, state, message, false).await
+        }
+    }
+
+    /// Handle a single JSON protocol request. Everything sent back while
+    /// handling it is marked with its id. Returns false if client should
+    /// disconnect.
+    async fn handle_request(
+        id: u32,
+        nickname: &mut String,
+        tx: &Outbox,
+        state: &ServerState,
+        line: &str,
+    ) -> bool {
+        let request = match Request::parse(line) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = ServerMessage::error(ErrorCode::InvalidRequest, e.reason);
+                let _ = tx.replying_to(e.id).send(error).await;
+                return true;
+            }
+        };
+
+        let tx = tx.replying_to(request.id);
+        match request.body {
+            RequestBody::Message { text } => {
+                Self::handle_chat(id, nickname, &tx, state, &text, true).await
+            }
+            RequestBody::Command { command, args } => {
+                let line = format!("/{} {}", command, args);
+                Self::handle_command(id, nickname, &tx, state, line.trim_end()).await
+            }
+        }
+    }
 
-            // Create and execute middleware chain
-            let middleware_chain = MiddlewareChain::default();
+    /// Handle a chat message, confirming delivery to the sender when
+    /// `acknowledge` is set. Returns false if client should disconnect
+    async fn handle_chat(
+        id: u32,
+        nickname: &mut String,
+        tx: &Outbox,
+        state: &ServerState,
+        message: &str,
+        acknowledge: bool,
+    ) -> bool {
+        // Read on every message since /join can move the client
+        let Some(channel) = state
+            .clients
+            .get(id, |client_state| client_state.channel.clone())
+        else {
+            return false;
+        };
 
-            if let Err(e) = middleware_chain.process(&mut ctx).await {
-                let _ = tx.send(format!("❌ {}\n", e)).await;
+        let mut ctx = MessageContext::new(state, id, nickname, Some(channel.clone()), message);
+
+        // Run the server's middleware chain, built once at startup
+        match state.middlewares.process(&mut ctx).await {
+            Ok(()) => {}
+            Err(MiddlewareError::Dropped) => {
+                println!("Dropped message from client {} ({})", id, nickname);
+                return true;
+            }
+            Err(e) => {
+                let _ = tx
+                    .send(ServerMessage::refused(e.code(), e.to_string()))
+                    .await;
                 return true; // Continue but don't send the message
             }
+        }
+        ctx.log_annotations();
+
+        match &ctx.delivery {
+            Delivery::Everyone => {
+                Self::broadcast_message(id, nickname, &channel, state, &ctx.message).await
+            }
+            // The sender never sees its own channel messages echoed, and
+            // is acknowledged just the same as when it was delivered
+            Delivery::SenderOnly => {
+                println!("Shadowed message from client {} ({})", id, nickname);
+            }
+            Delivery::Moderators { reason } => {
+                ctx.send_for_review(reason).await;
+                let _ = tx
+                    .send(ServerMessage::system(
+                        "⏳ Your message was held for moderator review",
+                    ))
+                    .await;
+                return true;
+            }
+        }
 
-            Self::broadcast_message(id, nickname, clients, &ctx.message).await;
-            true
+        if acknowledge {
+            let ack = ServerMessage::reply_with(
+                "message",
+                "✅ Message sent",
+                json!({ "channel": channel }),
+            );
+            let _ = tx.send(ack).await;
         }
+        true
     }
 
     /// Handle a command. Returns false if client should disconnect
     async fn handle_command(
         id: u32,
         nickname: &mut String,
-        tx: &mpsc::Sender<String>,
-        clients: &ClientMap,
+        tx: &Outbox,
+        state: &ServerState,
         command: &str,
     ) -> bool {
-        match Commands::handle_command(tx, nickname, command, clients, id).await {
+        // Arguments end up in nicknames, topics and reasons shown to others,
+        // so commands are always sanitized, whatever the middleware list says
+        let config = &state.config.sanitize;
+        let command = sanitize(command, config.mode, config.allow_colors);
+
+        match Commands::handle_command(tx, nickname, &command, state, id).await {
             Ok(should_continue) => should_continue,
             Err(e) => {
                 eprintln!("Error handling command for client {}: {}", id, e);
@@ -146,28 +417,117 @@ impl Client {
         }
     }
 
-    /// Broadcast a message to all other clients
-    async fn broadcast_message(id: u32, nickname: &str, clients: &ClientMap, message: &str) {
-        let broadcast_msg = format!("{}: {}\n", nickname, message);
-        println!("Broadcasting: {}", broadcast_msg.trim_end());
+    /// Broadcast a message to the other members of a channel and record it
+    /// in the history
+    async fn broadcast_message(
+        id: u32,
+        nickname: &str,
+        channel: &str,
+        state: &ServerState,
+        message: &str,
+    ) {
+        println!("Broadcasting to {}: {}: {}", channel, nickname, message);
+
+        state
+            .history
+            .lock()
+            .await
+            .push(HistoryEntry::new(channel, id, nickname, message));
 
-        let client_txs = {
-            let clients_lock = clients.lock().await;
-            clients_lock
-                .iter()
-                .filter(|(client_id, _)| **client_id != id)
-                .map(|(_, client_state)| client_state.tx.clone())
-                .collect::<Vec<_>>()
+        // Pushing never waits, so a slow reader cannot hold up the others
+        let broadcast_msg = Arc::new(OutboundMessage::new(ServerMessage::Chat {
+            channel: channel.to_string(),
+            sender_id: id,
+            sender: nickname.to_string(),
+            text: message.to_string(),
+        }));
+        state.clients.for_each(|client_id, client_state| {
+            if client_id != id && client_state.channel == channel {
+                let _ = client_state.tx.push(Arc::clone(&broadcast_msg));
+            }
+        });
+    }
+
+    /// Remove client from the shared ClientMap and its channel, and tell
+    /// the others it left
+    async fn disconnect_client(id: u32, reason: DisconnectReason, state: &ServerState) {
+        let client_state = {
+            // Lock order: channels before clients
+            let mut channels_lock = state.channels.lock().await;
+            let Some(client_state) = state.clients.remove(id) else {
+                return;
+            };
+            let still_occupied = state
+                .clients
+                .any(|_, other| other.channel == client_state.channel);
+            channels_lock.leave(&client_state.channel, id, still_occupied);
+            channels_lock.forget_client(id);
+            client_state
         };
+        println!(
+            "Client {} ({}) disconnected: {}",
+            id,
+            client_state.nickname(),
+            reason
+        );
 
-        for client_tx in client_txs {
-            let _ = client_tx.send(broadcast_msg.clone()).await;
+        // Everyone is leaving on shutdown, announcing each departure is noise
+        if reason != DisconnectReason::Shutdown {
+            let event = PresenceEvent::Left {
+                nickname: client_state.nickname().to_string(),
+                reason,
+            };
+            presence::announce(&state.clients, id, event).await;
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{OutputFormat, OverflowPolicy};
+
+    async fn register(state: &ServerState, id: u32, addr: &str) -> (String, Option<Mute>) {
+        let (tx, _rx) = outbox(4096, OverflowPolicy::DropOldest, OutputFormat::Plain);
+        let (control_tx, _control_rx) = mpsc::channel(1);
+        let mut nickname = format!("Client{}", id);
+        let mute = Client::register_client(
+            id,
+            &mut nickname,
+            &tx,
+            control_tx,
+            addr.parse().unwrap(),
+            Protocol::Text,
+            state,
+        )
+        .await;
+        (nickname, mute)
+    }
+
+    #[tokio::test]
+    async fn mute_survives_reconnecting_under_a_default_nickname() {
+        let state = ServerState::for_tests();
+        let (_, mute) = register(&state, 1, "10.0.0.1:5000").await;
+        assert!(mute.is_none());
+
+        let muted = state.clients.rename(1, "Alice");
+        assert!(muted.is_some());
+        crate::mutes::mute_automatically(&state.clients, &state.mutes, 1, Mute::new(None, None))
+            .await;
+        state.clients.remove(1);
+
+        // Back from the same address on another port, as a fresh ClientN
+        let (nickname, mute) = register(&state, 2, "10.0.0.1:6000").await;
+        assert_eq!(nickname, "Client2");
+        assert!(mute.is_some());
+        assert_eq!(state.clients.get(2, |client| client.is_muted()), Some(true));
 
-    /// Remove client from the shared ClientMap
-    async fn disconnect_client(id: u32, nickname: &str, clients: &ClientMap) {
-        println!("Client {} ({}) disconnected.", id, nickname);
-        clients.lock().await.remove(&id);
+        // Someone else's address is unaffected
+        let (_, mute) = register(&state, 3, "10.0.0.2:5000").await;
+        assert!(mute.is_none());
+        assert_eq!(
+            state.clients.get(3, |client| client.is_muted()),
+            Some(false)
+        );
     }
 }
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Number of shards the clients are spread over by id
+const SHARD_COUNT: usize = 16;
+
+/// Connected clients, sharded by id behind read-write locks, with an index
+/// from lowercased nickname to id
+///
+/// Locks are synchronous and only taken inside the methods below, so they
+/// can never be held across an `.await`. Callers pass closures that run
+/// while a shard is locked; those must not block. Sending to a client's
+/// outbox never waits, so fan-out is done inside `for_each`.
+///
+/// When both are needed, the nickname index is locked before a shard.
+pub(crate) struct ClientStore<C> {
+    shards: Vec<RwLock<HashMap<u32, C>>>,
+    nicknames: RwLock<HashMap<String, u32>>,
+    len: AtomicUsize,
+}
+
+/// Lock helpers that shrug off poisoning: nothing here leaves a shard
+/// half-updated, so a panic elsewhere shouldn't take the server down
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A client as the store sees it: something with a nickname to index
+pub(crate) trait Nicknamed {
+    fn nickname(&self) -> &str;
+
+    /// Only called by the store, which keeps its index in sync
+    fn set_nickname(&mut self, nickname: String);
+}
+
+impl<C: Nicknamed> ClientStore<C> {
+    pub(crate) fn new() -> Self {
+        Self {
+            shards: (0..SHARD_COUNT)
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            nicknames: RwLock::new(HashMap::new()),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn shard(&self, id: u32) -> &RwLock<HashMap<u32, C>> {
+        &self.shards[id as usize % SHARD_COUNT]
+    }
+
+    /// Add a client. If its nickname is already taken, underscores are
+    /// appended until it is unique. Returns the nickname it was stored under.
+    pub(crate) fn insert(&self, id: u32, mut client: C) -> String {
+        let mut nicknames = write(&self.nicknames);
+        let mut nickname = client.nickname().to_string();
+        while nicknames.contains_key(&nickname.to_lowercase()) {
+            nickname.push('_');
+        }
+        nicknames.insert(nickname.to_lowercase(), id);
+        client.set_nickname(nickname.clone());
+
+        if write(self.shard(id)).insert(id, client).is_none() {
+            self.len.fetch_add(1, Ordering::Relaxed);
+        }
+        nickname
+    }
+
+    /// Remove a client, returning its state
+    pub(crate) fn remove(&self, id: u32) -> Option<C> {
+        let mut nicknames = write(&self.nicknames);
+        let client = write(self.shard(id)).remove(&id)?;
+        nicknames.remove(&client.nickname().to_lowercase());
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        Some(client)
+    }
+
+    /// Number of connected clients
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Read one client's state
+    pub(crate) fn get<R>(&self, id: u32, f: impl FnOnce(&C) -> R) -> Option<R> {
+        read(self.shard(id)).get(&id).map(f)
+    }
+
+    /// Update one client's state. The nickname must be changed through
+    /// `rename` so the index stays in sync.
+    pub(crate) fn get_mut<R>(&self, id: u32, f: impl FnOnce(&mut C) -> R) -> Option<R> {
+        write(self.shard(id)).get_mut(&id).map(f)
+    }
+
+    /// Find a client's id by nickname, case-insensitively
+    pub(crate) fn find_by_nickname(&self, nickname: &str) -> Option<u32> {
+        read(&self.nicknames).get(&nickname.to_lowercase()).copied()
+    }
+
+    /// Change a client's nickname. Returns the old nickname, or None if the
+    /// new one belongs to another client or the client is gone.
+    pub(crate) fn rename(&self, id: u32, nickname: &str) -> Option<String> {
+        let mut nicknames = write(&self.nicknames);
+        let key = nickname.to_lowercase();
+        if nicknames.get(&key).is_some_and(|holder| *holder != id) {
+            return None;
+        }
+
+        let old_nickname = {
+            let mut shard = write(self.shard(id));
+            let client = shard.get_mut(&id)?;
+            let old_nickname = client.nickname().to_string();
+            client.set_nickname(nickname.to_string());
+            old_nickname
+        };
+        nicknames.remove(&old_nickname.to_lowercase());
+        nicknames.insert(key, id);
+        Some(old_nickname)
+    }
+
+    /// Visit every client, one shard at a time
+    pub(crate) fn for_each(&self, mut f: impl FnMut(u32, &C)) {
+        for shard in &self.shards {
+            for (id, client) in read(shard).iter() {
+                f(*id, client);
+            }
+        }
+    }
+
+    /// Visit every client mutably, one shard at a time
+    pub(crate) fn for_each_mut(&self, mut f: impl FnMut(u32, &mut C)) {
+        for shard in &self.shards {
+            for (id, client) in write(shard).iter_mut() {
+                f(*id, client);
+            }
+        }
+    }
+
+    /// Collect a value from every client that yields one
+    pub(crate) fn filter_map<T>(&self, mut f: impl FnMut(u32, &C) -> Option<T>) -> Vec<T> {
+        let mut values = Vec::new();
+        self.for_each(|id, client| values.extend(f(id, client)));
+        values
+    }
+
+    /// Check whether any client matches
+    pub(crate) fn any(&self, mut f: impl FnMut(u32, &C) -> bool) -> bool {
+        self.shards
+            .iter()
+            .any(|shard| read(shard).iter().any(|(id, client)| f(*id, client)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Client {
+        nickname: String,
+        messages: u32,
+    }
+
+    impl Client {
+        fn new(nickname: &str) -> Self {
+            Self {
+                nickname: nickname.to_string(),
+                messages: 0,
+            }
+        }
+    }
+
+    impl Nicknamed for Client {
+        fn nickname(&self) -> &str {
+            &self.nickname
+        }
+
+        fn set_nickname(&mut self, nickname: String) {
+            self.nickname = nickname;
+        }
+    }
+
+    /// Check that the index holds exactly the stored clients' nicknames
+    fn assert_consistent(store: &ClientStore<Client>) {
+        let mut stored = store.filter_map(|id, client| Some((client.nickname.to_lowercase(), id)));
+        stored.sort();
+        let mut indexed: Vec<_> = read(&store.nicknames)
+            .iter()
+            .map(|(nickname, id)| (nickname.clone(), *id))
+            .collect();
+        indexed.sort();
+        assert_eq!(stored, indexed);
+        assert_eq!(store.len(), stored.len());
+    }
+
+    #[test]
+    fn insert_appends_underscores_to_taken_nicknames() {
+        let store = ClientStore::new();
+        assert_eq!(store.insert(1, Client::new("Alice")), "Alice");
+        assert_eq!(store.insert(2, Client::new("alice")), "alice_");
+        assert_eq!(store.insert(3, Client::new("ALICE")), "ALICE__");
+        assert_eq!(
+            store.get(2, |client| client.nickname.clone()).unwrap(),
+            "alice_"
+        );
+        assert_eq!(store.find_by_nickname("Alice__"), Some(3));
+        assert_consistent(&store);
+    }
+
+    #[test]
+    fn lookups_ignore_case() {
+        let store = ClientStore::new();
+        store.insert(1, Client::new("Alice"));
+        assert_eq!(store.find_by_nickname("alice"), Some(1));
+        assert_eq!(store.find_by_nickname("ALICE"), Some(1));
+        assert_eq!(store.find_by_nickname("Bob"), None);
+    }
+
+    #[test]
+    fn rename_refuses_names_held_by_others_in_any_case() {
+        let store = ClientStore::new();
+        store.insert(1, Client::new("Alice"));
+        store.insert(2, Client::new("Bob"));
+        assert_eq!(store.rename(2, "ALICE"), None);
+        assert_eq!(
+            store.get(2, |client| client.nickname.clone()).unwrap(),
+            "Bob"
+        );
+
+        // Changing only the case of one's own nickname is allowed
+        assert_eq!(store.rename(1, "ALICE").as_deref(), Some("Alice"));
+        assert_eq!(store.find_by_nickname("alice"), Some(1));
+        assert_eq!(store.rename(3, "Carol"), None);
+        assert_consistent(&store);
+    }
+
+    #[test]
+    fn rename_and_remove_keep_the_index_in_sync() {
+        let store = ClientStore::new();
+        store.insert(1, Client::new("Alice"));
+        store.insert(17, Client::new("Bob"));
+
+        assert_eq!(store.rename(1, "Carol").as_deref(), Some("Alice"));
+        assert_eq!(store.find_by_nickname("Alice"), None);
+        assert_eq!(store.find_by_nickname("carol"), Some(1));
+        assert_consistent(&store);
+
+        // The old nickname is free for anyone again
+        assert_eq!(store.insert(2, Client::new("Alice")), "Alice");
+        assert!(store.remove(17).is_some());
+        assert!(store.remove(17).is_none());
+        assert_eq!(store.find_by_nickname("Bob"), None);
+        assert_consistent(&store);
+    }
+
+    #[test]
+    fn get_mut_leaves_the_index_alone() {
+        let store = ClientStore::new();
+        store.insert(1, Client::new("Alice"));
+        store.insert(2, Client::new("Bob"));
+
+        assert_eq!(
+            store.get_mut(1, |client| {
+                client.messages += 1;
+                client.messages
+            }),
+            Some(1)
+        );
+        store.for_each_mut(|_, client| client.messages += 1);
+        assert_eq!(store.get_mut(3, |client| client.messages), None);
+
+        assert_eq!(store.get(1, |client| client.messages), Some(2));
+        assert_eq!(store.find_by_nickname("Alice"), Some(1));
+        assert_consistent(&store);
+    }
+}
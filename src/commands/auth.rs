@@ -0,0 +1,160 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::{ClientControl, Role, ServerState},
+    traits::command_trait::CommandTrait,
+};
+
+/// Wrong passwords a connection may give before it is disconnected
+const MAX_FAILED_ATTEMPTS: u32 = 3;
+
+pub(crate) struct AuthCommand;
+
+impl CommandTrait for AuthCommand {
+    /// Creates a new instance of the AuthCommand.
+    fn new() -> Self {
+        AuthCommand
+    }
+
+    /// Become an admin using the password from the server configuration
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let Some(admin_password) = &state.config.admin_password else {
+            tx.send(ServerMessage::error(
+                ErrorCode::AuthFailed,
+                "Admin login is disabled on this server",
+            ))
+            .await?;
+            return Ok(());
+        };
+
+        if !constant_time_eq(args.trim().as_bytes(), admin_password.as_bytes()) {
+            let failures = state
+                .clients
+                .get_mut(client_id, |client_state| {
+                    client_state.failed_auths += 1;
+                    client_state.failed_auths
+                })
+                .unwrap_or(MAX_FAILED_ATTEMPTS);
+            println!(
+                "Failed admin login attempt {} by client {} ({})",
+                failures, client_id, nickname
+            );
+            tx.send(ServerMessage::error(
+                ErrorCode::AuthFailed,
+                "Invalid password",
+            ))
+            .await?;
+
+            // Commands are not rate limited, so cap guesses per connection
+            if failures >= MAX_FAILED_ATTEMPTS {
+                println!(
+                    "Disconnecting client {} ({}) after {} failed admin logins",
+                    client_id, nickname, failures
+                );
+                let control = state
+                    .clients
+                    .get(client_id, |client_state| client_state.control.clone());
+                if let Some(control) = control {
+                    let _ = control
+                        .send(ClientControl::Disconnect {
+                            notice: "🔒 Too many failed admin logins".to_string(),
+                        })
+                        .await;
+                }
+            }
+            return Ok(());
+        }
+
+        state
+            .clients
+            .get_mut(client_id, |client_state| client_state.set_role(Role::Admin));
+
+        println!("Client {} ({}) authenticated as admin", client_id, nickname);
+        tx.send(ServerMessage::reply("auth", "✅ You are now an admin"))
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Compare two byte strings in time that depends only on their lengths, so
+/// response timing does not reveal how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let diff = a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y));
+    a.len() == b.len() && std::hint::black_box(diff) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::config::{Config, OutputFormat, OverflowPolicy};
+    use crate::outbox::outbox;
+    use crate::protocol::Protocol;
+    use crate::shared_state::SharedClientState;
+
+    #[test]
+    fn constant_time_eq_compares_whole_strings() {
+        assert!(constant_time_eq(b"hunter2", b"hunter2"));
+        assert!(constant_time_eq(b"", b""));
+        assert!(!constant_time_eq(b"hunter2", b"hunter3"));
+        assert!(!constant_time_eq(b"hunter", b"hunter2"));
+        assert!(!constant_time_eq(b"hunter2", b""));
+    }
+
+    #[tokio::test]
+    async fn repeated_wrong_passwords_disconnect() {
+        let state = ServerState {
+            config: Arc::new(Config {
+                admin_password: Some("secret".to_string()),
+                ..Config::default()
+            }),
+            ..ServerState::for_tests()
+        };
+        let (tx, _rx) = outbox(4096, OverflowPolicy::DropOldest, OutputFormat::Plain);
+        let (control_tx, mut control_rx) = mpsc::channel(1);
+        let mut nickname = "Client1".to_string();
+        state.clients.insert(
+            1,
+            SharedClientState::new(
+                nickname.clone(),
+                tx.clone(),
+                control_tx,
+                "10.0.0.1:5000".parse().unwrap(),
+                Protocol::Text,
+            ),
+        );
+
+        let auth = AuthCommand::new();
+        for _ in 1..MAX_FAILED_ATTEMPTS {
+            auth.execute(&tx, &mut nickname, "guess", &state, 1)
+                .await
+                .unwrap();
+            assert!(control_rx.try_recv().is_err());
+        }
+        auth.execute(&tx, &mut nickname, "guess", &state, 1)
+            .await
+            .unwrap();
+        assert!(matches!(
+            control_rx.try_recv(),
+            Ok(ClientControl::Disconnect { .. })
+        ));
+        assert_eq!(
+            state.clients.get(1, |client_state| client_state.role()),
+            Some(Role::User)
+        );
+    }
+}
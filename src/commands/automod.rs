@@ -0,0 +1,122 @@
+use crate::automod::AutomodRules;
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::{Role, ServerState},
+    traits::command_trait::CommandTrait,
+};
+
+pub(crate) struct AutomodCommand;
+
+impl CommandTrait for AutomodCommand {
+    /// Creates a new instance of the AutomodCommand.
+    fn new() -> Self {
+        AutomodCommand
+    }
+
+    /// Requires the moderator role.
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    /// List the automod rules, dry-run them against some text, or reload
+    /// them from disk
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        _client_id: u32,
+    ) -> Result<(), BoxError> {
+        let (subcommand, rest) = args.split_once(' ').unwrap_or((args, ""));
+
+        match subcommand {
+            "" => {
+                let automod = state.automod.lock().await;
+                let mut message = format!(
+                    "🛡️  Automod: {} rule(s) from {}\n",
+                    automod.rules().len(),
+                    state.config.automod.path.display()
+                );
+                for rule in automod.rules() {
+                    message.push_str(&format!(
+                        "  {} [{}] /{}/ -> {}\n",
+                        rule.name,
+                        rule.scope,
+                        rule.pattern(),
+                        rule.action
+                    ));
+                }
+                drop(automod);
+
+                tx.send(ServerMessage::reply("automod", message)).await?;
+                Ok(())
+            }
+            "test" => {
+                let text = rest.trim();
+                if text.is_empty() {
+                    tx.send(ServerMessage::usage("Usage: /automod test <text>"))
+                        .await?;
+                    return Err("Missing automod test text".into());
+                }
+
+                // Evaluate against the caller's nickname without acting on it
+                let evaluation = state.automod.lock().await.evaluate(nickname, text);
+                let mut message = String::from("🧪 Automod test:\n");
+                if evaluation.fired.is_empty() {
+                    message.push_str("  No rules fired\n");
+                }
+                for rule in &evaluation.fired {
+                    message.push_str(&format!("  Rule '{}' fired: {}\n", rule.name, rule.action));
+                }
+                match evaluation.stopped_by() {
+                    Some(rule) => message.push_str(&format!(
+                        "  Result: message {} by '{}' ({})\n",
+                        rule.action.outcome(),
+                        rule.name,
+                        rule.reason
+                    )),
+                    None => message.push_str(&format!("  Result: {}\n", evaluation.message)),
+                }
+
+                tx.send(ServerMessage::reply("automod", message)).await?;
+                Ok(())
+            }
+            "reload" => {
+                // Load and compile outside the lock, keeping the current rules
+                // if the file is missing pieces or invalid
+                let path = state.automod.lock().await.path().to_path_buf();
+                match AutomodRules::load_in_background(path).await {
+                    Ok(automod) => {
+                        let count = automod.rules().len();
+                        *state.automod.lock().await = automod;
+                        tx.send(ServerMessage::reply(
+                            "automod",
+                            format!("✅ Automod rules reloaded, {} rule(s)", count),
+                        ))
+                        .await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to reload automod rules: {}", e);
+                        tx.send(ServerMessage::error(
+                            ErrorCode::Internal,
+                            format!("Failed to reload automod rules: {}", e),
+                        ))
+                        .await?;
+                        Err(e.into())
+                    }
+                }
+            }
+            _ => {
+                tx.send(ServerMessage::usage("Usage: /automod [test <text>|reload]"))
+                    .await?;
+                Err("Invalid automod arguments".into())
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use serde_json::json;
+
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::ban_list::{Ban, BanTarget};
+use crate::utils::duration::parse_duration_and_reason;
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::{ClientControl, Role, ServerState},
+    traits::command_trait::CommandTrait,
+    utils::target::ValidatedTarget,
+};
+
+pub(crate) struct BanCommand;
+
+impl CommandTrait for BanCommand {
+    /// Creates a new instance of the BanCommand.
+    fn new() -> Self {
+        BanCommand
+    }
+
+    /// Requires at least the moderator role.
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    /// Ban a nickname, IP address or CIDR range, kicking matching online users
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        // Split "<target> [duration] [reason]"
+        let mut parts = args.trim().splitn(2, ' ');
+        let target_input = parts.next().unwrap_or("").trim();
+        if target_input.is_empty() {
+            tx.send(ServerMessage::usage(
+                "Usage: /ban <user|ip|cidr> [duration e.g. 30m, 2h, 7d] [reason]",
+            ))
+            .await?;
+            return Err("Target cannot be empty".into());
+        }
+
+        let (duration, reason) = match parse_duration_and_reason(parts.next().unwrap_or("")) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                tx.send(ServerMessage::error(
+                    ErrorCode::InvalidArgument,
+                    e.to_string(),
+                ))
+                .await?;
+                return Ok(());
+            }
+        };
+
+        // Resolve an online user by ID or nickname so their current nickname
+        // and address are banned
+        let (target, last_ip) = match BanTarget::parse(target_input) {
+            BanTarget::Nickname(input) => {
+                let caller_ip = state
+                    .clients
+                    .get(client_id, |client_state| client_state.addr.ip());
+                let online = input
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|id| state.clients.get(*id, |_| ()).is_some())
+                    .or_else(|| state.clients.find_by_nickname(&input))
+                    .and_then(|id| {
+                        state.clients.get(id, |client_state| {
+                            (client_state.nickname().to_string(), client_state.addr.ip())
+                        })
+                    });
+                match online {
+                    // Never record the caller's own address, e.g. when behind the same NAT
+                    Some((nickname, ip)) => (
+                        BanTarget::Nickname(nickname),
+                        Some(ip).filter(|ip| Some(*ip) != caller_ip),
+                    ),
+                    None => (BanTarget::Nickname(input), None),
+                }
+            }
+            target => (target, None),
+        };
+
+        // Collect online users covered by the ban, refusing to ban the caller
+        // or anyone holding a role at least as high as theirs
+        let caller_role = state
+            .clients
+            .get(client_id, |client_state| client_state.role())
+            .unwrap_or_default();
+        let (own_ban, outranking, matching) = {
+            let mut own_ban = false;
+            let mut outranking = None;
+            let mut matching = Vec::new();
+            state.clients.for_each(|id, client_state| {
+                if !target.matches_nickname(client_state.nickname())
+                    && !target.matches_ip(client_state.addr.ip())
+                {
+                    return;
+                }
+                if id == client_id {
+                    own_ban = true;
+                } else if client_state.role() >= caller_role {
+                    outranking = Some((client_state.nickname().to_string(), client_state.role()));
+                } else {
+                    matching.push((
+                        client_state.nickname().to_string(),
+                        client_state.control.clone(),
+                    ));
+                }
+            });
+            (own_ban, outranking, matching)
+        };
+
+        if own_ban {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "You cannot ban yourself",
+            ))
+            .await?;
+            return Ok(());
+        }
+        if let Some((nickname, role)) = outranking {
+            tx.send(ServerMessage::refused(
+                ErrorCode::PermissionDenied,
+                format!("Permission denied: {} has the {} role", nickname, role),
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        // Persist the ban
+        let data = json!({
+            "target": target.to_string(),
+            "duration_secs": duration.map(|duration| duration.as_secs()),
+            "reason": reason,
+            "disconnected": matching.iter().map(|(nickname, _)| nickname).collect::<Vec<_>>(),
+        });
+        let mut ban = Ban::new(target, duration, reason);
+        if let Some(ip) = last_ip {
+            ban = ban.with_last_ip(ip);
+        }
+        let description = ban.describe();
+        let target_label = ban.target.to_string();
+        let snapshot = state.bans.lock().await.ban(ban);
+        if let Err(e) = snapshot.write().await {
+            eprintln!("Failed to save ban list: {}", e);
+            tx.send(ServerMessage::error(
+                ErrorCode::Internal,
+                "Failed to save ban list",
+            ))
+            .await?;
+            return Err(e.into());
+        }
+
+        // Kick everyone the ban covers
+        for (banned_nickname, control) in &matching {
+            let _ = control
+                .send(ClientControl::Kick {
+                    reason: Some(format!("banned{}", description)),
+                })
+                .await;
+
+            let broadcast_msg = format!(
+                "⛔ {} has been banned by a moderator{}\n",
+                banned_nickname, description
+            );
+            println!("Broadcasting: {}", broadcast_msg.trim());
+            ValidatedTarget::broadcast_to_all(&state.clients, &broadcast_msg).await?;
+        }
+
+        // Confirm to moderator
+        tx.send(ServerMessage::reply_with(
+            "ban",
+            format!(
+                "✅ Banned {}{}, {} user(s) disconnected",
+                target_label,
+                description,
+                matching.len()
+            ),
+            data,
+        ))
+        .await?;
+
+        Ok(())
+    }
+}
+
+pub(crate) struct UnbanCommand;
+
+impl CommandTrait for UnbanCommand {
+    /// Creates a new instance of the UnbanCommand.
+    fn new() -> Self {
+        UnbanCommand
+    }
+
+    /// Requires at least the moderator role.
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    /// Lift a ban on a nickname, IP address or CIDR range
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        _client_id: u32,
+    ) -> Result<(), BoxError> {
+        let target_input = args.trim();
+        if target_input.is_empty() {
+            tx.send(ServerMessage::usage("Usage: /unban <nickname|ip|cidr>"))
+                .await?;
+            return Err("Target cannot be empty".into());
+        }
+
+        let target = BanTarget::parse(target_input);
+        let snapshot = state.bans.lock().await.unban(&target);
+        let removed = snapshot.is_some();
+        let saved = match snapshot {
+            Some(snapshot) => snapshot.write().await,
+            None => Ok(()),
+        };
+        if let Err(e) = saved {
+            eprintln!("Failed to save ban list: {}", e);
+            tx.send(ServerMessage::error(
+                ErrorCode::Internal,
+                "Failed to save ban list",
+            ))
+            .await?;
+            return Err(e.into());
+        }
+
+        let message = if removed {
+            ServerMessage::reply_with(
+                "unban",
+                format!("✅ Unbanned {}", target),
+                json!({ "target": target.to_string() }),
+            )
+        } else {
+            ServerMessage::error(ErrorCode::NotFound, format!("No ban found for {}", target))
+        };
+        tx.send(message).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::channels::broadcast_to_channel;
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::{Role, ServerState},
+    traits::command_trait::CommandTrait,
+    utils::target::{Target, ValidatedTarget},
+};
+
+/// Get the caller's current channel if they may manage it: channel
+/// operators and server moderators. Reports the refusal to the caller.
+pub(super) async fn operated_channel(
+    tx: &Outbox,
+    state: &ServerState,
+    client_id: u32,
+) -> Result<Option<String>, BoxError> {
+    let Some((channel, role)) = state.clients.get(client_id, |client_state| {
+        (client_state.channel.clone(), client_state.role())
+    }) else {
+        return Ok(None);
+    };
+
+    let is_operator = role >= Role::Moderator
+        || state
+            .channels
+            .lock()
+            .await
+            .get(&channel)
+            .is_some_and(|channel_state| channel_state.is_operator(client_id));
+
+    if !is_operator {
+        tx.send(ServerMessage::refused(
+            ErrorCode::PermissionDenied,
+            format!("Permission denied: you are not an operator of {}", channel),
+        ))
+        .await?;
+        return Ok(None);
+    }
+    Ok(Some(channel))
+}
+
+/// Channel privileges an operator can hand out
+#[derive(Clone, Copy)]
+enum Privilege {
+    Operator,
+    Voice,
+}
+
+/// Grant or revoke a privilege for a member of the caller's channel
+async fn set_privilege(
+    tx: &Outbox,
+    nickname: &str,
+    args: &str,
+    state: &ServerState,
+    client_id: u32,
+    privilege: Privilege,
+    grant: bool,
+) -> Result<(), BoxError> {
+    let Some(target_input) = Target::from_args(args) else {
+        let command = match (privilege, grant) {
+            (Privilege::Operator, true) => "/op",
+            (Privilege::Operator, false) => "/deop",
+            (Privilege::Voice, true) => "/voice",
+            (Privilege::Voice, false) => "/devoice",
+        };
+        tx.send(ServerMessage::usage(format!(
+            "Usage: {} <user_id or nickname>",
+            command
+        )))
+        .await?;
+        return Err("Target cannot be empty".into());
+    };
+
+    let Some(channel) = operated_channel(tx, state, client_id).await? else {
+        return Ok(());
+    };
+    let target = ValidatedTarget::from_target(&target_input, tx, &state.clients).await?;
+
+    let in_channel = state
+        .clients
+        .get(target.id(), |client_state| client_state.channel == channel)
+        .unwrap_or(false);
+    if !in_channel {
+        tx.send(ServerMessage::error(
+            ErrorCode::NotFound,
+            format!("{} is not in {}", target.nickname(), channel),
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let changed = {
+        let mut channels_lock = state.channels.lock().await;
+        let Some(channel_state) = channels_lock.get_mut(&channel) else {
+            return Ok(());
+        };
+        match privilege {
+            Privilege::Operator => channel_state.set_operator(target.id(), grant),
+            Privilege::Voice => channel_state.set_voiced(target.id(), grant),
+        }
+    };
+
+    let label = match privilege {
+        Privilege::Operator => "operator status",
+        Privilege::Voice => "voice",
+    };
+    if !changed {
+        let state_label = if grant {
+            "already has"
+        } else {
+            "does not have"
+        };
+        tx.send(ServerMessage::error(
+            ErrorCode::InvalidArgument,
+            format!(
+                "{} {} {} in {}",
+                target.nickname(),
+                state_label,
+                label,
+                channel
+            ),
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let notice = if grant {
+        format!(
+            "⚙️  {} gave {} to {} in {}\n",
+            nickname,
+            label,
+            target.nickname(),
+            channel
+        )
+    } else {
+        format!(
+            "⚙️  {} removed {} from {} in {}\n",
+            nickname,
+            label,
+            target.nickname(),
+            channel
+        )
+    };
+    broadcast_to_channel(&state.clients, &channel, &notice).await;
+    Ok(())
+}
+
+pub(crate) struct OpCommand;
+
+impl CommandTrait for OpCommand {
+    /// Creates a new instance of the OpCommand.
+    fn new() -> Self {
+        OpCommand
+    }
+
+    /// Make a member of the caller's channel an operator
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        set_privilege(
+            tx,
+            nickname,
+            args,
+            state,
+            client_id,
+            Privilege::Operator,
+            true,
+        )
+        .await
+    }
+}
+
+pub(crate) struct DeopCommand;
+
+impl CommandTrait for DeopCommand {
+    /// Creates a new instance of the DeopCommand.
+    fn new() -> Self {
+        DeopCommand
+    }
+
+    /// Remove operator status from a member of the caller's channel
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        set_privilege(
+            tx,
+            nickname,
+            args,
+            state,
+            client_id,
+            Privilege::Operator,
+            false,
+        )
+        .await
+    }
+}
+
+pub(crate) struct VoiceCommand;
+
+impl CommandTrait for VoiceCommand {
+    /// Creates a new instance of the VoiceCommand.
+    fn new() -> Self {
+        VoiceCommand
+    }
+
+    /// Let a member speak while the caller's channel is moderated
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        set_privilege(tx, nickname, args, state, client_id, Privilege::Voice, true).await
+    }
+}
+
+pub(crate) struct DevoiceCommand;
+
+impl CommandTrait for DevoiceCommand {
+    /// Creates a new instance of the DevoiceCommand.
+    fn new() -> Self {
+        DevoiceCommand
+    }
+
+    /// Remove voice from a member of the caller's channel
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        set_privilege(
+            tx,
+            nickname,
+            args,
+            state,
+            client_id,
+            Privilege::Voice,
+            false,
+        )
+        .await
+    }
+}
@@ -0,0 +1,69 @@
+use serde_json::json;
+
+use crate::outbox::Outbox;
+use crate::server_message::ServerMessage;
+
+use crate::channels::member_counts;
+use crate::utils::error::BoxError;
+
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
+
+pub(crate) struct ChannelsCommand;
+
+impl CommandTrait for ChannelsCommand {
+    /// Creates a new instance of the ChannelsCommand.
+    fn new() -> Self {
+        ChannelsCommand
+    }
+
+    /// Lists channels that have members, with their member counts and modes.
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        _args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        // Lock order: channels before clients
+        let channels_lock = state.channels.lock().await;
+        let current = state
+            .clients
+            .get(client_id, |client_state| client_state.channel.clone());
+        let counts = member_counts(&state.clients);
+
+        let mut message = format!("Channels ({}):\n", counts.len());
+        let mut channels = Vec::with_capacity(counts.len());
+        for (channel, count) in &counts {
+            let marker = if Some(channel) == current.as_ref() {
+                " (current)"
+            } else {
+                ""
+            };
+            let modes = channels_lock
+                .get(channel)
+                .map(|channel_state| channel_state.modes.to_string())
+                .unwrap_or_else(|| "none".to_string());
+            message.push_str(&format!(
+                "  - {} ({} member(s), modes: {}){}\n",
+                channel, count, modes, marker
+            ));
+            channels.push(json!({
+                "name": channel,
+                "members": count,
+                "modes": modes,
+                "current": Some(channel) == current.as_ref(),
+            }));
+        }
+
+        drop(channels_lock);
+
+        tx.send(ServerMessage::reply_with(
+            "channels",
+            message,
+            json!({ "channels": channels }),
+        ))
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::utils::error::BoxError;
+use crate::word_list::WordList;
+
+use crate::{
+    shared_state::{Role, ServerState},
+    traits::command_trait::CommandTrait,
+};
+
+pub(crate) struct FilterCommand;
+
+impl CommandTrait for FilterCommand {
+    /// Creates a new instance of the FilterCommand.
+    fn new() -> Self {
+        FilterCommand
+    }
+
+    /// Requires the admin role.
+    fn required_role(&self) -> Role {
+        Role::Admin
+    }
+
+    /// Show the word filter status, or reload the word list from disk
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        _client_id: u32,
+    ) -> Result<(), BoxError> {
+        match args {
+            "" => {
+                let count = state.word_list.lock().await.len();
+                tx.send(ServerMessage::reply(
+                    "filter",
+                    format!(
+                        "🧹 Word filter: {} term(s) from {}, action: {}",
+                        count,
+                        state.config.word_filter.path.display(),
+                        state.config.word_filter.action
+                    ),
+                ))
+                .await?;
+                Ok(())
+            }
+            "reload" => {
+                // Read outside the lock and keep the current list if the
+                // file cannot be read
+                let path = state.word_list.lock().await.path().to_path_buf();
+                match WordList::load_in_background(path).await {
+                    Ok(word_list) => {
+                        let count = word_list.len();
+                        *state.word_list.lock().await = word_list;
+                        tx.send(ServerMessage::reply(
+                            "filter",
+                            format!("✅ Word list reloaded, {} term(s)", count),
+                        ))
+                        .await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to reload word list: {}", e);
+                        tx.send(ServerMessage::error(
+                            ErrorCode::Internal,
+                            format!("Failed to reload word list: {}", e),
+                        ))
+                        .await?;
+                        Err(e.into())
+                    }
+                }
+            }
+            _ => {
+                tx.send(ServerMessage::usage("Usage: /filter [reload]"))
+                    .await?;
+                Err("Invalid filter arguments".into())
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::config::OutputFormat;
+use crate::protocol::Protocol;
+use crate::utils::error::BoxError;
+
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
+
+pub(crate) struct FormatCommand;
+
+impl CommandTrait for FormatCommand {
+    /// Create a new instance of the FormatCommand.
+    fn new() -> Self {
+        FormatCommand
+    }
+
+    /// Show or change how the server's messages are rendered for the caller
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        if args.is_empty() {
+            tx.send(ServerMessage::reply(
+                "format",
+                format!("🖥️  Output format: {}", tx.format()),
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        let format = match args.parse::<OutputFormat>() {
+            Ok(format) => format,
+            Err(e) => {
+                tx.send(ServerMessage::error(
+                    ErrorCode::InvalidArgument,
+                    format!("{}\nUsage: /format [plain|colored|json]", e),
+                ))
+                .await?;
+                return Err(e.into());
+            }
+        };
+
+        // Programs on the JSON protocol rely on every line being JSON
+        let protocol = state
+            .clients
+            .get(client_id, |client_state| client_state.protocol);
+        if protocol == Some(Protocol::Json) {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "The output format cannot be changed on a JSON protocol connection",
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        // Messages are rendered as they are written, so this reply and
+        // anything already queued use the new format
+        tx.set_format(format);
+        tx.send(ServerMessage::reply(
+            "format",
+            format!("✅ Output format is now {}", format),
+        ))
+        .await?;
+        Ok(())
+    }
+}
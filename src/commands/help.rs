@@ -1,8 +1,9 @@
-use tokio::sync::mpsc;
+use crate::outbox::Outbox;
+use crate::server_message::ServerMessage;
 
 use crate::utils::error::BoxError;
 
-use crate::{shared_state::ClientMap, traits::command_trait::CommandTrait};
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
 
 pub(crate) struct HelpCommand;
 
@@ -14,26 +15,40 @@ impl CommandTrait for HelpCommand {
     /// Display this help message
     async fn execute(
         &self,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         _nickname: &mut String,
         _args: &str,
-        _clients: &ClientMap,
+        _state: &ServerState,
         _client_id: u32,
     ) -> Result<(), BoxError> {
         let help_message = "Available commands:\n
             /help - Display this help message\n
             /nickname <new_nickname> - Change your nickname\n
             /quit - Disconnect from the server\n
-            /list - List all connected users\n
-            /message <user> <message> - Send a private message to a user [NOT_IMPLEMENTED]\n
+            /list [#channel] - List connected users, optionally in one channel\n
+            /join #channel [key] - Switch to a channel, creating it as its operator if needed\n
+            /part [#channel] - Leave your channel and return to #general\n
+            /channels - List channels and their member counts\n
+            /topic [text] - Show or set your channel's topic\n
+            /mode [+|-][i|k|m|t] [key] - Show or change your channel's modes [operator]\n
+            /invite <user> - Invite a user to your channel [operator]\n
+            /op <user>, /deop <user> - Grant or remove channel operator status [operator]\n
+            /voice <user>, /devoice <user> - Let a user speak in a moderated channel [operator]\n
+            /msg <user> <message> - Send a private message to a user (alias: /message)\n
             /broadcast <message> - Send a message to all connected users [NOT_IMPLEMENTED]\n
-            /kick <user> - Kick a user from the server [NOT_IMPLEMENTED]\n
-            /ban <user> - Ban a user from the server [NOT_IMPLEMENTED]\n
-            /unban <user> - Unban a user from the server [NOT_IMPLEMENTED]\n
-            /mute <user> - Mute a user [NOT_IMPLEMENTED]\n
-            /unmute <user> - Unmute a user [NOT_IMPLEMENTED]\n
-            /history <user> - View a user's chat history [NOT_IMPLEMENTED]\n".to_string();
-        tx.send(help_message).await?;
+            /kick <user> [reason] - Kick a user from the server [moderator]\n
+            /ban <user|ip|cidr> [duration] [reason] - Ban a user from the server [moderator]\n
+            /unban <nickname|ip|cidr> - Unban a user from the server [moderator]\n
+            /mute <user> [duration] [reason] - Mute a user, optionally for e.g. 10m or 2h [moderator]\n
+            /unmute <user|ip> - Unmute a user, or lift the mute on an address [moderator]\n
+            /auth <password> - Authenticate as a server admin\n
+            /role <user> <user|moderator|admin> - Change a user's role [admin]\n
+            /filter [reload] - Show the word filter or reload its word list [admin]\n
+            /automod [test <text>|reload] - List, dry-run or reload the automod rules [moderator]\n
+            /history [user] [count] [page] - View recent chat history\n
+            /presence [on|off] - Show or hide join, leave and rename announcements\n
+            /format [plain|colored|json] - Show or change how messages are shown to you\n".to_string();
+        tx.send(ServerMessage::reply("help", help_message)).await?;
         Ok(())
     }
 }
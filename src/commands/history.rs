@@ -0,0 +1,96 @@
+use serde_json::json;
+
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::history::send_entries;
+
+use crate::utils::error::BoxError;
+
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
+
+/// Messages shown per page when no count is given
+const DEFAULT_COUNT: usize = 20;
+/// Upper bound on messages shown per page
+const MAX_COUNT: usize = 100;
+
+pub(crate) struct HistoryCommand;
+
+impl CommandTrait for HistoryCommand {
+    /// Creates a new instance of the HistoryCommand.
+    fn new() -> Self {
+        HistoryCommand
+    }
+
+    /// Show recent public messages in the caller's channel, optionally
+    /// filtered by sender
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        // Parse "[user] [count] [page]"; a leading number is the count
+        let mut parts = args.split_whitespace().peekable();
+        let sender = parts
+            .next_if(|part| part.parse::<usize>().is_err())
+            .map(str::to_string);
+        let numbers: Result<Vec<usize>, _> = parts.map(str::parse::<usize>).collect();
+
+        let (count, page) = match numbers.as_deref() {
+            Ok([]) => (DEFAULT_COUNT, 1),
+            Ok([count]) => (*count, 1),
+            Ok([count, page]) => (*count, *page),
+            _ => {
+                tx.send(ServerMessage::usage(
+                    "Usage: /history [user] [count] [page]",
+                ))
+                .await?;
+                return Err("Invalid history arguments".into());
+            }
+        };
+
+        if count == 0 || page == 0 {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "Count and page must be at least 1",
+            ))
+            .await?;
+            return Ok(());
+        }
+        let count = count.min(MAX_COUNT);
+
+        let Some(channel) = state
+            .clients
+            .get(client_id, |client_state| client_state.channel.clone())
+        else {
+            return Ok(());
+        };
+
+        let history = state.history.lock().await;
+        let entries = history.page(&channel, sender.as_deref(), count, page);
+
+        let header = match &sender {
+            Some(sender) => format!("📜 History for {} in {} (page {}):", sender, channel, page),
+            None => format!("📜 History for {} (page {}):", channel, page),
+        };
+        let footer = if entries.is_empty() {
+            "(No messages)\n"
+        } else {
+            ""
+        };
+        let data = json!({
+            "channel": channel,
+            "sender": sender,
+            "page": page,
+            "count": entries.len(),
+        });
+        send_entries(tx, &entries, header, footer, |text| {
+            ServerMessage::reply_with("history", text, data)
+        })
+        .await?;
+        Ok(())
+    }
+}
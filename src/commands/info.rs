@@ -1,7 +1,10 @@
-use tokio::sync::mpsc::Sender;
+use serde_json::json;
+
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
 
 use crate::{
-    shared_state::ClientMap,
+    shared_state::ServerState,
     traits::command_trait::CommandTrait,
     utils::error::BoxError,
     utils::target::{Target, ValidatedTarget},
@@ -18,47 +21,64 @@ impl CommandTrait for InfoCommand {
     /// Execute the info command.
     async fn execute(
         &self,
-        tx: &Sender<String>,
+        tx: &Outbox,
         _nickname: &mut String,
         args: &str,
-        clients: &ClientMap,
+        state: &ServerState,
         _client_id: u32,
     ) -> Result<(), BoxError> {
         // Parse target - can be either ID or name
         let target_input = Target::from_args(args);
 
         if target_input.is_none() {
-            tx.send(
-                "Error: Target cannot be empty\nUsage: /info <user_id or nickname>\n".to_string(),
-            )
+            tx.send(ServerMessage::usage(
+                "Target cannot be empty\nUsage: /info <user_id or nickname>",
+            ))
             .await?;
             return Err("Target cannot be empty".into());
         }
 
         // Validate target
-        let target = ValidatedTarget::from_target(&target_input.unwrap(), tx, clients).await?;
+        let target =
+            ValidatedTarget::from_target(&target_input.unwrap(), tx, &state.clients).await?;
 
         // Get the client's shared state
-        let clients_lock = clients.lock().await;
-        let client_state = clients_lock.get(&target.id());
-
-        let message = if let Some(state) = client_state {
-            format!(
-                "📋 Info for {} (ID: {}):\n  • Nickname: {}\n  • Muted: {}\n",
-                target.nickname(),
-                target.id(),
-                state.nickname,
-                if state.is_muted() {
-                    "Yes ⚠️"
-                } else {
-                    "No ✅"
-                }
+        let info = state.clients.get(target.id(), |target_state| {
+            let mute = target_state.active_mute();
+            let data = json!({
+                "user_id": target.id(),
+                "nickname": target_state.nickname(),
+                "channel": target_state.channel,
+                "role": target_state.role().to_string(),
+                "muted": mute.is_some(),
+                "mute_remaining_secs": mute
+                    .as_ref()
+                    .and_then(|mute| mute.remaining())
+                    .map(|remaining| remaining.as_secs()),
+                "dropped_lines": target_state.tx.dropped(),
+            });
+            ServerMessage::reply_with(
+                "info",
+                format!(
+                    "📋 Info for {} (ID: {}):\n  • Nickname: {}\n  • Channel: {}\n  • Role: {}\n  • Muted: {}\n  • Dropped lines: {}",
+                    target.nickname(),
+                    target.id(),
+                    target_state.nickname(),
+                    target_state.channel,
+                    target_state.role(),
+                    match &mute {
+                        Some(mute) => format!("Yes ⚠️{}", mute.describe()),
+                        None => "No ✅".to_string(),
+                    },
+                    target_state.tx.dropped()
+                ),
+                data,
             )
-        } else {
-            "Error: No information found for user\n".to_string()
-        };
+        });
+        let message = info.unwrap_or_else(|| {
+            ServerMessage::error(ErrorCode::NotFound, "No information found for user")
+        });
 
-        drop(clients_lock);
         tx.send(message).await?;
         Ok(())
     }
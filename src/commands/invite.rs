@@ -0,0 +1,62 @@
+use crate::outbox::Outbox;
+use crate::server_message::ServerMessage;
+
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::ServerState,
+    traits::command_trait::CommandTrait,
+    utils::target::{Target, ValidatedTarget},
+};
+
+use super::channel_ops::operated_channel;
+
+pub(crate) struct InviteCommand;
+
+impl CommandTrait for InviteCommand {
+    /// Creates a new instance of the InviteCommand.
+    fn new() -> Self {
+        InviteCommand
+    }
+
+    /// Invite a user to the caller's channel, letting them in while it is invite-only
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let Some(target_input) = Target::from_args(args) else {
+            tx.send(ServerMessage::usage("Usage: /invite <user_id or nickname>"))
+                .await?;
+            return Err("Target cannot be empty".into());
+        };
+
+        let Some(channel) = operated_channel(tx, state, client_id).await? else {
+            return Ok(());
+        };
+        let target = ValidatedTarget::from_target(&target_input, tx, &state.clients).await?;
+
+        if let Some(channel_state) = state.channels.lock().await.get_mut(&channel) {
+            channel_state.invite(target.id());
+        }
+
+        target
+            .send_message(
+                &state.clients,
+                ServerMessage::system(format!(
+                    "📨 {} invited you to {}, type /join {} to accept",
+                    nickname, channel, channel
+                )),
+            )
+            .await?;
+        tx.send(ServerMessage::reply(
+            "invite",
+            format!("✅ Invited {} to {}", target.nickname(), channel),
+        ))
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::channels::{parse_channel_name, DEFAULT_CHANNEL};
+use crate::presence::{self, PresenceEvent};
+use crate::shared_state::Role;
+use crate::utils::error::BoxError;
+
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
+
+pub(crate) struct JoinCommand;
+
+impl CommandTrait for JoinCommand {
+    /// Create a new instance of the JoinCommand.
+    fn new() -> Self {
+        JoinCommand
+    }
+
+    /// Switch to a channel, creating it if nobody is in it yet.
+    /// Keyed channels take the key as a second argument.
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let mut parts = args.split_whitespace();
+        let channel = match parse_channel_name(parts.next().unwrap_or("")) {
+            Ok(channel) => channel,
+            Err(e) => {
+                tx.send(ServerMessage::error(
+                    ErrorCode::InvalidArgument,
+                    format!("{}\nUsage: /join #channel [key]", e),
+                ))
+                .await?;
+                return Err(e.into());
+            }
+        };
+        let key = parts.next();
+
+        switch_channel("join", tx, nickname, state, client_id, &channel, key).await
+    }
+}
+
+pub(crate) struct PartCommand;
+
+impl CommandTrait for PartCommand {
+    /// Create a new instance of the PartCommand.
+    fn new() -> Self {
+        PartCommand
+    }
+
+    /// Leave the current channel and return to the default one
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let Some(current) = state
+            .clients
+            .get(client_id, |client_state| client_state.channel.clone())
+        else {
+            return Ok(());
+        };
+
+        // An explicit channel must be the one the client is in
+        if !args.is_empty() {
+            match parse_channel_name(args) {
+                Ok(channel) if channel == current => {}
+                Ok(channel) => {
+                    tx.send(ServerMessage::error(
+                        ErrorCode::InvalidArgument,
+                        format!("You are not in {}", channel),
+                    ))
+                    .await?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    tx.send(ServerMessage::error(
+                        ErrorCode::InvalidArgument,
+                        format!("{}\nUsage: /part [#channel]", e),
+                    ))
+                    .await?;
+                    return Err(e.into());
+                }
+            }
+        }
+
+        if current == DEFAULT_CHANNEL {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                format!("{} cannot be left", DEFAULT_CHANNEL),
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        switch_channel(
+            "part",
+            tx,
+            nickname,
+            state,
+            client_id,
+            DEFAULT_CHANNEL,
+            None,
+        )
+        .await
+    }
+}
+
+/// Move a client to another channel if its modes allow it, announce it in
+/// both channels and show the new channel's topic and recent messages.
+/// `command` is the command the replies answer.
+async fn switch_channel(
+    command: &'static str,
+    tx: &Outbox,
+    nickname: &str,
+    state: &ServerState,
+    client_id: u32,
+    channel: &str,
+    key: Option<&str>,
+) -> Result<(), BoxError> {
+    let (previous, topic) = {
+        // Lock order: channels before clients
+        let mut channels_lock = state.channels.lock().await;
+        let switched = state.clients.get_mut(client_id, |client_state| {
+            if client_state.channel == channel {
+                return Err(format!("You are already in {}", channel));
+            }
+            // Server moderators are not held back by invites and keys
+            let bypass = client_state.role() >= Role::Moderator;
+            channels_lock
+                .admit(channel, client_id, key, bypass)
+                .map_err(|e| e.to_string())?;
+            Ok(std::mem::replace(
+                &mut client_state.channel,
+                channel.to_string(),
+            ))
+        });
+        let previous = match switched {
+            Some(Ok(previous)) => previous,
+            Some(Err(refusal)) => {
+                drop(channels_lock);
+                tx.send(ServerMessage::error(
+                    ErrorCode::PermissionDenied,
+                    refusal.to_string(),
+                ))
+                .await?;
+                return Ok(());
+            }
+            None => return Ok(()),
+        };
+
+        let still_occupied = state
+            .clients
+            .any(|_, client_state| client_state.channel == previous);
+        channels_lock.leave(&previous, client_id, still_occupied);
+
+        let topic = channels_lock
+            .get(channel)
+            .and_then(|channel_state| channel_state.topic.clone());
+        (previous, topic)
+    };
+
+    let parted = PresenceEvent::PartedChannel {
+        nickname: nickname.to_string(),
+        channel: previous,
+    };
+    presence::announce(&state.clients, client_id, parted).await;
+    let joined = PresenceEvent::JoinedChannel {
+        nickname: nickname.to_string(),
+        channel: channel.to_string(),
+    };
+    presence::announce(&state.clients, client_id, joined).await;
+
+    tx.send(ServerMessage::reply(
+        command,
+        format!("✅ You are now chatting in {}", channel),
+    ))
+    .await?;
+    if let Some(topic) = topic {
+        tx.send(ServerMessage::reply(
+            command,
+            format!("📌 Topic for {}: {}", channel, topic),
+        ))
+        .await?;
+    }
+
+    state
+        .history
+        .lock()
+        .await
+        .send_replay(tx, channel, |text| ServerMessage::reply(command, text))
+        .await?;
+    Ok(())
+}
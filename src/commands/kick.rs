@@ -0,0 +1,107 @@
+use serde_json::json;
+
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::{ClientControl, Role, ServerState},
+    traits::command_trait::CommandTrait,
+    utils::target::{Target, ValidatedTarget},
+};
+
+pub(crate) struct KickCommand;
+
+impl CommandTrait for KickCommand {
+    /// Creates a new instance of the KickCommand.
+    fn new() -> Self {
+        KickCommand
+    }
+
+    /// Requires at least the moderator role.
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    /// Forcibly disconnect a user
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        // Split "<user> [reason]"
+        let mut parts = args.trim().splitn(2, ' ');
+        let target_input = match parts.next().and_then(Target::from_args) {
+            Some(target) => target,
+            None => {
+                tx.send(ServerMessage::usage(
+                    "Usage: /kick <user_id or nickname> [reason]",
+                ))
+                .await?;
+                return Err("Target cannot be empty".into());
+            }
+        };
+        let reason = parts
+            .next()
+            .map(str::trim)
+            .filter(|reason| !reason.is_empty())
+            .map(str::to_string);
+
+        // Validate target
+        let target = ValidatedTarget::from_target(&target_input, tx, &state.clients).await?;
+
+        if target.id() == client_id {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "You cannot kick yourself, use /quit instead",
+            ))
+            .await?;
+            return Ok(());
+        }
+        if !target.outranked_by(&state.clients, client_id, tx).await? {
+            return Ok(());
+        }
+
+        // Signal the target's connection task to disconnect
+        target
+            .send_control(
+                &state.clients,
+                ClientControl::Kick {
+                    reason: reason.clone(),
+                },
+            )
+            .await?;
+
+        // Broadcast to all clients
+        let broadcast_msg = match &reason {
+            Some(reason) => format!(
+                "👢 {} has been kicked by a moderator: {}\n",
+                target.nickname(),
+                reason
+            ),
+            None => format!("👢 {} has been kicked by a moderator.\n", target.nickname()),
+        };
+        println!("Broadcasting: {}", broadcast_msg.trim());
+        ValidatedTarget::broadcast_to_all(&state.clients, &broadcast_msg).await?;
+
+        // Confirm to moderator
+        let message = format!(
+            "✅ Kicked user {} (ID: {})\n",
+            target.nickname(),
+            target.id()
+        );
+        let data = json!({
+            "user_id": target.id(),
+            "nickname": target.nickname(),
+            "reason": reason,
+        });
+        tx.send(ServerMessage::reply_with("kick", message, data))
+            .await?;
+
+        Ok(())
+    }
+}
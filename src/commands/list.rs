@@ -1,8 +1,12 @@
-use tokio::sync::mpsc;
+use serde_json::json;
 
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::channels::parse_channel_name;
 use crate::utils::error::BoxError;
 
-use crate::{shared_state::ClientMap, traits::command_trait::CommandTrait};
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
 
 pub(crate) struct ListCommand;
 
@@ -12,31 +16,68 @@ impl CommandTrait for ListCommand {
         ListCommand
     }
 
-    /// Lists connected users.
+    /// Lists connected users, optionally only those in one channel.
     async fn execute(
         &self,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         _nickname: &mut String,
-        _args: &str,
-        clients: &ClientMap,
+        args: &str,
+        state: &ServerState,
         _client_id: u32,
     ) -> Result<(), BoxError> {
-        let clients_lock = clients.lock().await;
-        let count = clients_lock.len();
+        let channel = if args.is_empty() {
+            None
+        } else {
+            match parse_channel_name(args) {
+                Ok(channel) => Some(channel),
+                Err(e) => {
+                    tx.send(ServerMessage::error(
+                        ErrorCode::InvalidArgument,
+                        format!("{}\nUsage: /list [#channel]", e),
+                    ))
+                    .await?;
+                    return Err(e.into());
+                }
+            }
+        };
 
-        let mut list_message = format!("Connected users ({}):\n", count);
+        let mut members: Vec<_> = state.clients.filter_map(|id, client_state| {
+            channel
+                .as_deref()
+                .is_none_or(|channel| client_state.channel == channel)
+                .then(|| {
+                    (
+                        id,
+                        client_state.nickname().to_string(),
+                        client_state.channel.clone(),
+                    )
+                })
+        });
+        members.sort_unstable_by_key(|(id, _, _)| *id);
+        let count = members.len();
+        let users: Vec<_> = members
+            .iter()
+            .map(|(id, nickname, channel)| {
+                json!({ "user_id": id, "nickname": nickname, "channel": channel })
+            })
+            .collect();
+
+        let mut list_message = match &channel {
+            Some(channel) => format!("Users in {} ({}):\n", channel, count),
+            None => format!("Connected users ({}):\n", count),
+        };
 
         if count == 0 {
             list_message.push_str("(No users currently connected)\n");
         } else {
-            for (id, client_state) in clients_lock.iter() {
-                list_message.push_str(&format!("  - {} (ID: {})\n", client_state.nickname, id));
+            for (id, nickname, channel) in members {
+                list_message.push_str(&format!("  - {} (ID: {}) in {}\n", nickname, id, channel));
             }
         }
 
-        drop(clients_lock);
-
-        tx.send(list_message).await?;
+        let data = json!({ "channel": channel, "users": users });
+        tx.send(ServerMessage::reply_with("list", list_message, data))
+            .await?;
         Ok(())
     }
 }
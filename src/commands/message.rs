@@ -0,0 +1,116 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::middlewares::{Delivery, MessageContext};
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::ServerState,
+    traits::{command_trait::CommandTrait, middleware_trait::MiddlewareError},
+    utils::target::{Target, ValidatedTarget},
+};
+
+pub(crate) struct MessageCommand;
+
+impl CommandTrait for MessageCommand {
+    /// Creates a new instance of the MessageCommand.
+    fn new() -> Self {
+        MessageCommand
+    }
+
+    /// Send a private message to a single user
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        // Split "<user> <message>"
+        let mut parts = args.trim().splitn(2, ' ');
+        let target_input = parts.next().and_then(Target::from_args);
+        let text = parts.next().map(str::trim).unwrap_or("");
+
+        let target_input = match target_input {
+            Some(target) if !text.is_empty() => target,
+            _ => {
+                tx.send(ServerMessage::usage(
+                    "Usage: /msg <user_id or nickname> <message>",
+                ))
+                .await?;
+                return Err("Missing target or message".into());
+            }
+        };
+
+        // Validate target
+        let target = ValidatedTarget::from_target(&target_input, tx, &state.clients).await?;
+
+        if target.id() == client_id {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "You cannot send a private message to yourself",
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        // Run the text through the same middleware as public chat
+        let mut ctx = MessageContext::new(state, client_id, nickname, None, text);
+
+        match state.middlewares.process(&mut ctx).await {
+            Ok(()) => {}
+            Err(MiddlewareError::Dropped) => {
+                println!("Dropped private message from client {}", client_id);
+                return Ok(());
+            }
+            Err(e) => {
+                tx.send(ServerMessage::refused(e.code(), e.to_string()))
+                    .await?;
+                return Ok(());
+            }
+        }
+        ctx.log_annotations();
+
+        match &ctx.delivery {
+            // Deliver to the recipient only
+            Delivery::Everyone => {
+                let private_msg = ServerMessage::Private {
+                    sender_id: client_id,
+                    sender: nickname.clone(),
+                    recipient_id: target.id(),
+                    recipient: target.nickname().to_string(),
+                    text: ctx.message.clone(),
+                    outgoing: false,
+                };
+                target.send_message(&state.clients, private_msg).await?;
+            }
+            // Confirm as usual so the sender cannot tell
+            Delivery::SenderOnly => {
+                println!("Shadowed private message from client {}", client_id);
+            }
+            Delivery::Moderators { reason } => {
+                ctx.send_for_review(reason).await;
+                tx.send(ServerMessage::reply(
+                    "msg",
+                    "⏳ Your message was held for moderator review",
+                ))
+                .await?;
+                return Ok(());
+            }
+        }
+
+        // Confirm to sender
+        tx.send(ServerMessage::Private {
+            sender_id: client_id,
+            sender: nickname.clone(),
+            recipient_id: target.id(),
+            recipient: target.nickname().to_string(),
+            text: ctx.message,
+            outgoing: true,
+        })
+        .await?;
+
+        Ok(())
+    }
+}
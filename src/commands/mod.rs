@@ -1,52 +1,129 @@
+use crate::middlewares::moderation::flood_control::check_flood;
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+use crate::utils::rate_limit::FloodKind;
 use crate::{
-    shared_state::ClientMap,
-    traits::command_trait::CommandTrait,
-    utils::error::BoxError,
-    utils::target::{Target, TargetId},
+    shared_state::ServerState, traits::command_trait::CommandTrait, utils::error::BoxError,
+    utils::target::Target,
 };
-use tokio::sync::mpsc;
 
+mod auth;
+mod automod;
+mod ban;
+mod channel_ops;
+mod channels;
+mod filter;
+mod format;
 mod help;
+mod history;
 mod info;
+mod invite;
+mod join;
+mod kick;
 mod list;
+mod message;
+mod mode;
 mod mute;
 mod nick;
+mod presence;
 mod quit;
+mod role;
+mod topic;
 
+use auth::AuthCommand;
+use automod::AutomodCommand;
+use ban::{BanCommand, UnbanCommand};
+use channel_ops::{DeopCommand, DevoiceCommand, OpCommand, VoiceCommand};
+use channels::ChannelsCommand;
+use filter::FilterCommand;
+use format::FormatCommand;
 use help::HelpCommand;
+use history::HistoryCommand;
 use info::InfoCommand;
+use invite::InviteCommand;
+use join::{JoinCommand, PartCommand};
+use kick::KickCommand;
 use list::ListCommand;
+use message::MessageCommand;
+use mode::ModeCommand;
 use mute::{MuteCommand, UnmuteCommand};
 use nick::NicknameCommand;
+use presence::PresenceCommand;
 use quit::QuitCommand;
+use role::RoleCommand;
+use topic::TopicCommand;
 
 /// Enum representing the commands available in the chat system
 pub(crate) enum Commands {
     Help,
     Quit,
     Nickname(String),
-    List,
-    Mute(TargetId),
-    Unmute(TargetId),
+    List(String),
+    Mute(String),
+    Unmute(Target),
     Info(Target),
+    Message(String),
+    Kick(String),
+    Ban(String),
+    Unban(String),
+    Auth(String),
+    Role(String),
+    History(String),
+    Presence(String),
+    Format(String),
+    Join(String),
+    Part(String),
+    Channels,
+    Invite(String),
+    Mode(String),
+    Topic(String),
+    Op(String),
+    Deop(String),
+    Voice(String),
+    Devoice(String),
+    Filter(String),
+    Automod(String),
 }
 
 impl Commands {
     /// Handles execution of a parsed command from user input.
     /// Returns Ok(true) to continue running, Ok(false) to disconnect.
     pub(crate) async fn handle_command(
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         nickname: &mut String,
         input: &str,
-        clients: &ClientMap,
+        state: &ServerState,
         client_id: u32,
     ) -> Result<bool, BoxError> {
-        match Self::parse(input) {
-            Some(command) => command.execute(tx, nickname, clients, client_id).await,
+        let command = Self::parse(input);
+
+        // Commands have their own, stricter rate limit. Leaving is never
+        // refused.
+        if !matches!(command, Some(Commands::Quit)) {
+            let notice = check_flood(
+                &state.clients,
+                &state.mutes,
+                client_id,
+                FloodKind::Command,
+                &state.config.flood,
+            )
+            .await;
+            if let Some(notice) = notice {
+                tx.send(ServerMessage::refused(ErrorCode::RateLimited, notice))
+                    .await?;
+                return Ok(true);
+            }
+        }
+
+        match command {
+            Some(command) => command.execute(tx, nickname, state, client_id).await,
             None => {
-                tx.send(format!(
-                    "Unrecognized command: {}. Use /help to see available commands.\n",
-                    input
+                tx.send(ServerMessage::error(
+                    ErrorCode::UnknownCommand,
+                    format!(
+                        "Unrecognized command: {}. Use /help to see available commands.",
+                        input
+                    ),
                 ))
                 .await?;
                 Ok(true)
@@ -62,13 +139,78 @@ impl Commands {
         match *command {
             "/help" => Some(Commands::Help),
             "/quit" => Some(Commands::Quit),
-            "/nick" | "/nickname" => {
-                parts.get(1).map(|new_nickname| Commands::Nickname(new_nickname.trim().to_string()))
-            }
-            "/list" => Some(Commands::List),
+            "/nick" | "/nickname" => parts
+                .get(1)
+                .map(|new_nickname| Commands::Nickname(new_nickname.trim().to_string())),
+            "/list" => Some(Commands::List(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
             "/info" => Target::from_args(parts.get(1).unwrap_or(&"")).map(Commands::Info),
-            "/mute" => TargetId::from_args(parts.get(1).unwrap_or(&"")).map(Commands::Mute),
-            "/unmute" => TargetId::from_args(parts.get(1).unwrap_or(&"")).map(Commands::Unmute),
+            "/mute" => parts
+                .get(1)
+                .map(|args| Commands::Mute(args.trim().to_string())),
+            "/unmute" => Target::from_args(parts.get(1).unwrap_or(&"")).map(Commands::Unmute),
+            "/msg" | "/message" => parts
+                .get(1)
+                .map(|args| Commands::Message(args.trim().to_string())),
+            "/kick" => parts
+                .get(1)
+                .map(|args| Commands::Kick(args.trim().to_string())),
+            "/ban" => parts
+                .get(1)
+                .map(|args| Commands::Ban(args.trim().to_string())),
+            "/unban" => parts
+                .get(1)
+                .map(|args| Commands::Unban(args.trim().to_string())),
+            "/auth" => parts
+                .get(1)
+                .map(|args| Commands::Auth(args.trim().to_string())),
+            "/role" => parts
+                .get(1)
+                .map(|args| Commands::Role(args.trim().to_string())),
+            "/history" => Some(Commands::History(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
+            "/presence" => Some(Commands::Presence(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
+            "/format" => Some(Commands::Format(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
+            "/join" => parts
+                .get(1)
+                .map(|args| Commands::Join(args.trim().to_string())),
+            "/part" => Some(Commands::Part(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
+            "/channels" => Some(Commands::Channels),
+            "/invite" => parts
+                .get(1)
+                .map(|args| Commands::Invite(args.trim().to_string())),
+            "/mode" => Some(Commands::Mode(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
+            "/topic" => Some(Commands::Topic(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
+            "/op" => parts
+                .get(1)
+                .map(|args| Commands::Op(args.trim().to_string())),
+            "/deop" => parts
+                .get(1)
+                .map(|args| Commands::Deop(args.trim().to_string())),
+            "/voice" => parts
+                .get(1)
+                .map(|args| Commands::Voice(args.trim().to_string())),
+            "/devoice" => parts
+                .get(1)
+                .map(|args| Commands::Devoice(args.trim().to_string())),
+            "/filter" => Some(Commands::Filter(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
+            "/automod" => Some(Commands::Automod(
+                parts.get(1).unwrap_or(&"").trim().to_string(),
+            )),
             _ => None,
         }
     }
@@ -77,54 +219,178 @@ impl Commands {
     /// Returns Ok(true) to continue running, Ok(false) to disconnect.
     async fn execute(
         &self,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         nickname: &mut String,
-        clients: &ClientMap,
+        state: &ServerState,
         client_id: u32,
     ) -> Result<bool, BoxError> {
         match self {
             Commands::Help => {
-                HelpCommand
-                    .execute(tx, nickname, "", clients, client_id)
-                    .await?;
+                Self::run(&HelpCommand, tx, nickname, "", state, client_id).await?;
                 Ok(true)
             }
             Commands::Quit => {
-                QuitCommand
-                    .execute(tx, nickname, "", clients, client_id)
-                    .await?;
+                Self::run(&QuitCommand, tx, nickname, "", state, client_id).await?;
                 Ok(false) // Signal to disconnect
             }
             Commands::Nickname(new_nickname) => {
-                NicknameCommand
-                    .execute(tx, nickname, new_nickname, clients, client_id)
-                    .await?;
+                Self::run(
+                    &NicknameCommand,
+                    tx,
+                    nickname,
+                    new_nickname,
+                    state,
+                    client_id,
+                )
+                .await?;
                 Ok(true)
             }
-            Commands::List => {
-                ListCommand
-                    .execute(tx, nickname, "", clients, client_id)
-                    .await?;
+            Commands::List(args) => {
+                Self::run(&ListCommand, tx, nickname, args, state, client_id).await?;
                 Ok(true)
             }
-            Commands::Mute(target_id) => {
-                MuteCommand
-                    .execute(tx, nickname, &target_id.0, clients, client_id)
-                    .await?;
+            Commands::Mute(args) => {
+                Self::run(&MuteCommand, tx, nickname, args, state, client_id).await?;
                 Ok(true)
             }
             Commands::Info(target) => {
-                InfoCommand
-                    .execute(tx, nickname, target.as_str(), clients, client_id)
-                    .await?;
+                Self::run(
+                    &InfoCommand,
+                    tx,
+                    nickname,
+                    target.as_str(),
+                    state,
+                    client_id,
+                )
+                .await?;
                 Ok(true)
             }
-            Commands::Unmute(target_id) => {
-                UnmuteCommand
-                    .execute(tx, nickname, &target_id.0, clients, client_id)
-                    .await?;
+            Commands::Unmute(target) => {
+                Self::run(
+                    &UnmuteCommand,
+                    tx,
+                    nickname,
+                    target.as_str(),
+                    state,
+                    client_id,
+                )
+                .await?;
                 Ok(true)
             }
+            Commands::Message(args) => {
+                Self::run(&MessageCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Kick(args) => {
+                Self::run(&KickCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Ban(args) => {
+                Self::run(&BanCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Unban(args) => {
+                Self::run(&UnbanCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Auth(args) => {
+                Self::run(&AuthCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Role(args) => {
+                Self::run(&RoleCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::History(args) => {
+                Self::run(&HistoryCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Presence(args) => {
+                Self::run(&PresenceCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Format(args) => {
+                Self::run(&FormatCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Join(args) => {
+                Self::run(&JoinCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Part(args) => {
+                Self::run(&PartCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Channels => {
+                Self::run(&ChannelsCommand, tx, nickname, "", state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Invite(args) => {
+                Self::run(&InviteCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Mode(args) => {
+                Self::run(&ModeCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Topic(args) => {
+                Self::run(&TopicCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Op(args) => {
+                Self::run(&OpCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Deop(args) => {
+                Self::run(&DeopCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Voice(args) => {
+                Self::run(&VoiceCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Devoice(args) => {
+                Self::run(&DevoiceCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Filter(args) => {
+                Self::run(&FilterCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+            Commands::Automod(args) => {
+                Self::run(&AutomodCommand, tx, nickname, args, state, client_id).await?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Runs a command after checking the caller's role against the role it requires.
+    async fn run<C: CommandTrait>(
+        command: &C,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let required = command.required_role();
+        let role = state
+            .clients
+            .get(client_id, |client_state| client_state.role())
+            .unwrap_or_default();
+
+        if role < required {
+            tx.send(ServerMessage::refused(
+                ErrorCode::PermissionDenied,
+                format!(
+                    "Permission denied: this command requires the {} role",
+                    required
+                ),
+            ))
+            .await?;
+            return Ok(());
         }
+
+        command.execute(tx, nickname, args, state, client_id).await
     }
 }
@@ -0,0 +1,126 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::channels::{broadcast_to_channel, DEFAULT_CHANNEL};
+use crate::utils::error::BoxError;
+
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
+
+use super::channel_ops::operated_channel;
+
+const USAGE: &str = "Usage: /mode [+|-][i|k|m|t] [key]\n\
+  i - invite-only, k - requires a key, m - moderated, t - topic locked to operators\n";
+
+pub(crate) struct ModeCommand;
+
+impl CommandTrait for ModeCommand {
+    /// Creates a new instance of the ModeCommand.
+    fn new() -> Self {
+        ModeCommand
+    }
+
+    /// Show the caller's channel modes, or change them as an operator
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let mut parts = args.split_whitespace();
+        let Some(spec) = parts.next() else {
+            return show_modes(tx, state, client_id).await;
+        };
+        let key = parts.next();
+
+        let (grant, letters) = match spec.split_at_checked(1) {
+            Some(("+", letters)) if !letters.is_empty() => (true, letters),
+            Some(("-", letters)) if !letters.is_empty() => (false, letters),
+            _ => {
+                tx.send(ServerMessage::usage(USAGE.to_string())).await?;
+                return Err("Invalid mode arguments".into());
+            }
+        };
+        if let Some(unknown) = letters.chars().find(|letter| !"ikmt".contains(*letter)) {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                format!("Unknown channel mode '{}'\n{}", unknown, USAGE),
+            ))
+            .await?;
+            return Err("Invalid mode arguments".into());
+        }
+        if grant && letters.contains('k') && key.is_none() {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "Mode +k needs a key, e.g. /mode +k secret",
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        let Some(channel) = operated_channel(tx, state, client_id).await? else {
+            return Ok(());
+        };
+
+        // Everyone starts in the default channel, so it must stay open
+        if channel == DEFAULT_CHANNEL && grant && (letters.contains('i') || letters.contains('k')) {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                format!("{} cannot be invite-only or keyed", DEFAULT_CHANNEL),
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        let modes = {
+            let mut channels_lock = state.channels.lock().await;
+            let Some(channel_state) = channels_lock.get_mut(&channel) else {
+                return Ok(());
+            };
+            let modes = &mut channel_state.modes;
+            for letter in letters.chars() {
+                match letter {
+                    'i' => modes.invite_only = grant,
+                    'k' => modes.key = key.filter(|_| grant).map(str::to_string),
+                    'm' => modes.moderated = grant,
+                    't' => modes.topic_locked = grant,
+                    _ => unreachable!("mode letters are validated above"),
+                }
+            }
+            modes.to_string()
+        };
+
+        let notice = format!(
+            "⚙️  {} set mode {} on {} (now {})\n",
+            nickname, spec, channel, modes
+        );
+        broadcast_to_channel(&state.clients, &channel, &notice).await;
+        Ok(())
+    }
+}
+
+/// Send the modes of the caller's channel
+async fn show_modes(tx: &Outbox, state: &ServerState, client_id: u32) -> Result<(), BoxError> {
+    let Some(channel) = state
+        .clients
+        .get(client_id, |client_state| client_state.channel.clone())
+    else {
+        return Ok(());
+    };
+
+    let modes = state
+        .channels
+        .lock()
+        .await
+        .get(&channel)
+        .map(|channel_state| channel_state.modes.to_string())
+        .unwrap_or_else(|| "none".to_string());
+
+    tx.send(ServerMessage::reply(
+        "mode",
+        format!("⚙️  Modes for {}: {}", channel, modes),
+    ))
+    .await?;
+    Ok(())
+}
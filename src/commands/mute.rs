@@ -1,11 +1,18 @@
-use tokio::sync::mpsc;
+use std::net::IpAddr;
 
+use serde_json::json;
+
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::mutes::Mute;
+use crate::utils::duration::parse_duration_and_reason;
 use crate::utils::error::BoxError;
 
 use crate::{
-    shared_state::ClientMap,
+    shared_state::{Role, ServerState},
     traits::command_trait::CommandTrait,
-    utils::target::{TargetId, ValidatedTarget},
+    utils::target::{Target, ValidatedTarget},
 };
 
 pub(crate) struct MuteCommand;
@@ -16,42 +23,93 @@ impl CommandTrait for MuteCommand {
         MuteCommand
     }
 
-    /// Mute a user
+    /// Requires at least the moderator role.
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    /// Mute a user, optionally for a limited time
     async fn execute(
         &self,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         _nickname: &mut String,
         args: &str,
-        clients: &ClientMap,
-        _client_id: u32,
+        state: &ServerState,
+        client_id: u32,
     ) -> Result<(), BoxError> {
-        // Parse and validate target
-        let target_id = TargetId(args.to_string());
-        let target = ValidatedTarget::from_target_id(&target_id, tx, clients).await?;
+        // Split "<user> [duration] [reason]"
+        let mut parts = args.trim().splitn(2, ' ');
+        let Some(target_input) = parts.next().and_then(Target::from_args) else {
+            tx.send(ServerMessage::usage(
+                "Usage: /mute <user_id or nickname> [duration e.g. 10m, 2h] [reason]",
+            ))
+            .await?;
+            return Err("Target cannot be empty".into());
+        };
+        let (duration, reason) = match parse_duration_and_reason(parts.next().unwrap_or("")) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                tx.send(ServerMessage::error(
+                    ErrorCode::InvalidArgument,
+                    e.to_string(),
+                ))
+                .await?;
+                return Ok(());
+            }
+        };
+
+        // Validate target
+        let target = ValidatedTarget::from_target(&target_input, tx, &state.clients).await?;
+        if !target.outranked_by(&state.clients, client_id, tx).await? {
+            return Ok(());
+        }
 
-        // Mute the target user
-        let mut clients_lock = clients.lock().await;
-        if let Some(client_state) = clients_lock.get_mut(&target.id()) {
-            client_state.mute();
+        // Mute the target user, replacing the expiry of an existing mute
+        let data = json!({
+            "user_id": target.id(),
+            "nickname": target.nickname(),
+            "duration_secs": duration.map(|duration| duration.as_secs()),
+            "reason": reason,
+        });
+        let mute = Mute::new(duration, reason);
+        let description = mute.describe();
+        let target_ip = state.clients.get_mut(target.id(), |client_state| {
+            client_state.mute(mute.clone());
+            client_state.addr.ip()
+        });
+
+        // Record the mute on the address so reconnecting does not lift it
+        if let Some(ip) = target_ip {
+            state.mutes.lock().await.mute(ip, target.nickname(), mute);
         }
-        drop(clients_lock);
 
         // Send notification to the muted user
-        let notification = "⚠️  You have been muted by a moderator. You cannot send messages.\n";
-        target.send_message(clients, notification).await?;
+        let notification = format!(
+            "⚠️  You have been muted by a moderator{}. You cannot send messages.",
+            description
+        );
+        target
+            .send_message(&state.clients, ServerMessage::system(notification))
+            .await?;
 
         // Broadcast to all clients
-        let broadcast_msg = format!("🔇 {} has been muted by a moderator.\n", target.nickname());
+        let broadcast_msg = format!(
+            "🔇 {} has been muted by a moderator{}.\n",
+            target.nickname(),
+            description
+        );
         println!("Broadcasting: {}", broadcast_msg.trim());
-        ValidatedTarget::broadcast_to_all(clients, &broadcast_msg).await?;
+        ValidatedTarget::broadcast_to_all(&state.clients, &broadcast_msg).await?;
 
         // Confirm to moderator
         let message = format!(
-            "✅ Muted user {} (ID: {})\n",
+            "✅ Muted user {} (ID: {}){}\n",
             target.nickname(),
-            target.id()
+            target.id(),
+            description
         );
-        tx.send(message).await?;
+        tx.send(ServerMessage::reply_with("mute", message, data))
+            .await?;
 
         Ok(())
     }
@@ -65,34 +123,54 @@ impl CommandTrait for UnmuteCommand {
         UnmuteCommand
     }
 
-    /// Unmute a user
+    /// Requires at least the moderator role.
+    fn required_role(&self) -> Role {
+        Role::Moderator
+    }
+
+    /// Unmute a user, or lift every mute recorded at an address
     async fn execute(
         &self,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         _nickname: &mut String,
         args: &str,
-        clients: &ClientMap,
+        state: &ServerState,
         _client_id: u32,
     ) -> Result<(), BoxError> {
-        // Parse and validate target
-        let target_id = TargetId(args.to_string());
-        let target = ValidatedTarget::from_target_id(&target_id, tx, clients).await?;
+        if let Ok(ip) = args.trim().parse::<IpAddr>() {
+            return unmute_address(tx, state, ip).await;
+        }
 
-        // Unmute the target user
-        let mut clients_lock = clients.lock().await;
-        if let Some(client_state) = clients_lock.get_mut(&target.id()) {
+        // Parse and validate target
+        let Some(target_input) = Target::from_args(args) else {
+            tx.send(ServerMessage::usage(
+                "Usage: /unmute <user_id, nickname or ip>",
+            ))
+            .await?;
+            return Err("Target cannot be empty".into());
+        };
+        let target = ValidatedTarget::from_target(&target_input, tx, &state.clients).await?;
+
+        // Unmute the target user and forget the mute recorded on their
+        // address, whatever nickname it was placed on
+        let target_ip = state.clients.get_mut(target.id(), |client_state| {
             client_state.unmute();
+            client_state.addr.ip()
+        });
+        if let Some(ip) = target_ip {
+            state.mutes.lock().await.unmute(ip);
         }
-        drop(clients_lock);
 
         // Send notification to the unmuted user
-        let notification = "✅ You have been unmuted. You can now send messages.\n";
-        target.send_message(clients, notification).await?;
+        let notification = "✅ You have been unmuted. You can now send messages.";
+        target
+            .send_message(&state.clients, ServerMessage::system(notification))
+            .await?;
 
         // Broadcast to all clients
         let broadcast_msg = format!("🔊 {} has been unmuted.\n", target.nickname());
         println!("Broadcasting: {}", broadcast_msg.trim());
-        ValidatedTarget::broadcast_to_all(clients, &broadcast_msg).await?;
+        ValidatedTarget::broadcast_to_all(&state.clients, &broadcast_msg).await?;
 
         // Confirm to moderator
         let message = format!(
@@ -100,8 +178,58 @@ impl CommandTrait for UnmuteCommand {
             target.nickname(),
             target.id()
         );
-        tx.send(message).await?;
+        let data = json!({
+            "user_id": target.id(),
+            "nickname": target.nickname(),
+        });
+        tx.send(ServerMessage::reply_with("unmute", message, data))
+            .await?;
 
         Ok(())
     }
 }
+
+/// Lift the mute recorded at `ip`, along with those of its online users
+async fn unmute_address(tx: &Outbox, state: &ServerState, ip: IpAddr) -> Result<(), BoxError> {
+    let ip = ip.to_canonical();
+    let Some(record) = state.mutes.lock().await.unmute(ip) else {
+        tx.send(ServerMessage::error(
+            ErrorCode::NotFound,
+            format!("No mute found for {}", ip),
+        ))
+        .await?;
+        return Ok(());
+    };
+
+    // Pushing never waits, so the unmuted users are told while the shard is held
+    let mut unmuted = Vec::new();
+    state.clients.for_each_mut(|_, client_state| {
+        if client_state.addr.ip().to_canonical() == ip && client_state.unmute() {
+            let notification = "✅ You have been unmuted. You can now send messages.";
+            let _ = client_state.tx.push(ServerMessage::system(notification));
+            unmuted.push(client_state.nickname().to_string());
+        }
+    });
+
+    for nickname in &unmuted {
+        let broadcast_msg = format!("🔊 {} has been unmuted.\n", nickname);
+        println!("Broadcasting: {}", broadcast_msg.trim());
+        ValidatedTarget::broadcast_to_all(&state.clients, &broadcast_msg).await?;
+    }
+
+    let data = json!({
+        "ip": ip.to_string(),
+        "nickname": record.nickname,
+        "unmuted": unmuted,
+    });
+    tx.send(ServerMessage::reply_with(
+        "unmute",
+        format!(
+            "✅ Lifted the mute on {} (placed on {})",
+            ip, record.nickname
+        ),
+        data,
+    ))
+    .await?;
+    Ok(())
+}
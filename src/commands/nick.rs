@@ -1,8 +1,11 @@
-use tokio::sync::mpsc::Sender;
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
 
-use crate::utils::error::BoxError;
+use crate::automod::notify_moderators;
+use crate::presence::{self, PresenceEvent};
+use crate::utils::error::{BoxError, ChatError};
 
-use crate::{shared_state::ClientMap, traits::command_trait::CommandTrait};
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
 
 pub(crate) struct NicknameCommand;
 
@@ -15,25 +18,32 @@ impl CommandTrait for NicknameCommand {
     /// Change the user's nickname with full validation.
     async fn execute(
         &self,
-        tx: &Sender<String>,
+        tx: &Outbox,
         nickname: &mut String,
         args: &str,
-        clients: &ClientMap,
+        state: &ServerState,
         client_id: u32,
     ) -> Result<(), BoxError> {
         let new_nickname = args.trim();
 
         // Validation: empty check
         if new_nickname.is_empty() {
-            tx.send("Error: Nickname cannot be empty\n".to_string())
-                .await?;
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "Nickname cannot be empty",
+            ))
+            .await?;
             return Ok(());
         }
 
         // Validation: length check
-        if new_nickname.len() > 20 {
-            tx.send("Error: Nickname too long (max 20 chars)\n".to_string())
-                .await?;
+        let max = state.config.max_nickname_length;
+        if new_nickname.chars().count() > max {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                ChatError::NicknameTooLong { max }.to_string(),
+            ))
+            .await?;
             return Ok(());
         }
 
@@ -42,45 +52,93 @@ impl CommandTrait for NicknameCommand {
             .chars()
             .all(|c| c.is_alphanumeric() || c == '_')
         {
-            tx.send(
-                "Error: Nickname can only contain letters, numbers, and underscores\n".to_string(),
-            )
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "Nickname can only contain letters, numbers, and underscores",
+            ))
             .await?;
             return Ok(());
         }
 
-        // Validation: uniqueness check (case-insensitive)
-        let clients_lock = clients.lock().await;
-        let is_taken = clients_lock.iter().any(|(id, state)| {
-            *id != client_id && state.nickname.eq_ignore_ascii_case(new_nickname)
-        });
-        drop(clients_lock);
+        // Validation: nickname ban check
+        let ban = state.bans.lock().await.find_nickname(new_nickname).cloned();
+        if let Some(ban) = ban {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                format!("Nickname '{}' is banned{}", new_nickname, ban.describe()),
+            ))
+            .await?;
+            return Ok(());
+        }
 
-        if is_taken {
-            tx.send(format!(
-                "Error: Nickname '{}' is already in use\n",
-                new_nickname
+        // Validation: automod nickname rules
+        let forbidden = state.automod.lock().await.forbidding_nickname(new_nickname);
+        if let Some(rule) = forbidden {
+            let report = format!(
+                "Rule '{}' refused renaming {} to {}",
+                rule.name, nickname, new_nickname
+            );
+            notify_moderators(&state.clients, "automod", &report).await;
+            tx.send(ServerMessage::refused(
+                ErrorCode::Blocked,
+                format!(
+                    "Nickname '{}' is not allowed: {}",
+                    new_nickname, rule.reason
+                ),
             ))
             .await?;
             return Ok(());
         }
 
-        // Update nickname
-        let old_nickname = std::mem::replace(nickname, new_nickname.to_string());
+        // Rename in the ClientMap; the nickname index makes the case-insensitive
+        // uniqueness check and the update a single step
+        let Some(old_nickname) = state.clients.rename(client_id, new_nickname) else {
+            tx.send(ServerMessage::error(
+                ErrorCode::NicknameTaken,
+                format!("Nickname '{}' is already in use", new_nickname),
+            ))
+            .await?;
+            return Ok(());
+        };
+        *nickname = new_nickname.to_string();
 
-        // Update in ClientMap
-        let mut clients_lock = clients.lock().await;
-        if let Some(client_state) = clients_lock.get_mut(&client_id) {
-            client_state.nickname = new_nickname.to_string();
-        }
-        drop(clients_lock);
+        // A mute on the address follows every nickname used from it
+        let ip = state
+            .clients
+            .get(client_id, |client_state| client_state.addr.ip());
+        let mute = match ip {
+            Some(ip) => state.mutes.lock().await.find(ip).cloned(),
+            None => None,
+        };
+        let reapplied = mute.filter(|mute| {
+            state
+                .clients
+                .get_mut(client_id, |client_state| client_state.mute(mute.clone()))
+                .unwrap_or(false)
+        });
 
         // Confirm to user
-        tx.send(format!(
-            "✅ Nickname changed from '{}' to '{}'\n",
-            old_nickname, nickname
+        tx.send(ServerMessage::reply(
+            "nick",
+            format!(
+                "✅ Nickname changed from '{}' to '{}'",
+                old_nickname, nickname
+            ),
         ))
         .await?;
+        if let Some(mute) = reapplied {
+            let notice = format!(
+                "⚠️  You are still muted{}. You cannot send messages.",
+                mute.describe()
+            );
+            tx.send(ServerMessage::system(notice)).await?;
+        }
+
+        let event = PresenceEvent::Renamed {
+            old_nickname,
+            new_nickname: nickname.clone(),
+        };
+        presence::announce(&state.clients, client_id, event).await;
 
         Ok(())
     }
@@ -0,0 +1,53 @@
+use crate::outbox::Outbox;
+use crate::server_message::ServerMessage;
+
+use crate::utils::error::BoxError;
+
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
+
+pub(crate) struct PresenceCommand;
+
+impl CommandTrait for PresenceCommand {
+    /// Create a new instance of the PresenceCommand.
+    fn new() -> Self {
+        PresenceCommand
+    }
+
+    /// Show or hide join, leave and rename announcements, toggling when no
+    /// argument is given
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let requested = match args.to_ascii_lowercase().as_str() {
+            "" => None,
+            "on" => Some(true),
+            "off" => Some(false),
+            _ => {
+                tx.send(ServerMessage::usage("Usage: /presence [on|off]"))
+                    .await?;
+                return Err("Invalid presence arguments".into());
+            }
+        };
+
+        let Some(show) = state.clients.get_mut(client_id, |client_state| {
+            let show = requested.unwrap_or(!client_state.show_presence());
+            client_state.set_show_presence(show);
+            show
+        }) else {
+            return Ok(());
+        };
+
+        let message = if show {
+            "✅ Join, leave and rename announcements are now shown"
+        } else {
+            "✅ Join, leave and rename announcements are now hidden"
+        };
+        tx.send(ServerMessage::reply("presence", message)).await?;
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
-use tokio::sync::mpsc;
+use crate::outbox::Outbox;
+use crate::server_message::ServerMessage;
 
 use crate::utils::error::BoxError;
 
-use crate::{shared_state::ClientMap, traits::command_trait::CommandTrait};
+use crate::{shared_state::ServerState, traits::command_trait::CommandTrait};
 
 pub(crate) struct QuitCommand;
 
@@ -14,14 +15,18 @@ impl CommandTrait for QuitCommand {
     /// Quit the chat.
     async fn execute(
         &self,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         nickname: &mut String,
         _args: &str,
-        _clients: &ClientMap,
+        _state: &ServerState,
         _client_id: u32,
     ) -> Result<(), BoxError> {
         println!("Quitting the chat...");
-        tx.send(format!("{} has left the chat.", nickname)).await?;
+        tx.send(ServerMessage::reply(
+            "quit",
+            format!("{} has left the chat.", nickname),
+        ))
+        .await?;
         *nickname = String::new();
         Ok(())
     }
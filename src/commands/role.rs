@@ -0,0 +1,106 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::{Role, ServerState},
+    traits::command_trait::CommandTrait,
+    utils::target::{Target, ValidatedTarget},
+};
+
+pub(crate) struct RoleCommand;
+
+impl CommandTrait for RoleCommand {
+    /// Creates a new instance of the RoleCommand.
+    fn new() -> Self {
+        RoleCommand
+    }
+
+    /// Requires the admin role.
+    fn required_role(&self) -> Role {
+        Role::Admin
+    }
+
+    /// Assign a role to a user
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        _nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        // Split "<user> <role>"
+        let mut parts = args.split_whitespace();
+        let (target_input, role) = match (parts.next().and_then(Target::from_args), parts.next()) {
+            (Some(target), Some(role)) => (target, role),
+            _ => {
+                tx.send(ServerMessage::usage(
+                    "Usage: /role <user_id or nickname> <user|moderator|admin>",
+                ))
+                .await?;
+                return Err("Missing target or role".into());
+            }
+        };
+
+        let role: Role = match role.parse() {
+            Ok(role) => role,
+            Err(e) => {
+                tx.send(ServerMessage::error(
+                    ErrorCode::InvalidArgument,
+                    e.to_string(),
+                ))
+                .await?;
+                return Err(e.into());
+            }
+        };
+
+        // Validate target
+        let target = ValidatedTarget::from_target(&target_input, tx, &state.clients).await?;
+
+        if target.id() == client_id {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                "You cannot change your own role",
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        // Update the target's role
+        let changed = state
+            .clients
+            .get_mut(target.id(), |client_state| client_state.set_role(role))
+            .unwrap_or(false);
+
+        if !changed {
+            tx.send(ServerMessage::reply(
+                "role",
+                format!("{} already has the {} role", target.nickname(), role),
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        // Send notification to the target user
+        let notification = format!("🎖️  Your role is now {}", role);
+        target
+            .send_message(&state.clients, ServerMessage::system(notification))
+            .await?;
+
+        // Confirm to admin
+        tx.send(ServerMessage::reply(
+            "role",
+            format!(
+                "✅ {} (ID: {}) is now {}",
+                target.nickname(),
+                target.id(),
+                role
+            ),
+        ))
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, ServerMessage};
+
+use crate::channels::broadcast_to_channel;
+use crate::utils::error::BoxError;
+
+use crate::{
+    shared_state::{Role, ServerState},
+    traits::command_trait::CommandTrait,
+};
+
+/// Maximum topic length in characters
+const MAX_TOPIC_LENGTH: usize = 200;
+
+pub(crate) struct TopicCommand;
+
+impl CommandTrait for TopicCommand {
+    /// Creates a new instance of the TopicCommand.
+    fn new() -> Self {
+        TopicCommand
+    }
+
+    /// Show the caller's channel topic, or set it. Only operators may set
+    /// the topic while the channel is +t.
+    async fn execute(
+        &self,
+        tx: &Outbox,
+        nickname: &mut String,
+        args: &str,
+        state: &ServerState,
+        client_id: u32,
+    ) -> Result<(), BoxError> {
+        let Some((channel, role)) = state.clients.get(client_id, |client_state| {
+            (client_state.channel.clone(), client_state.role())
+        }) else {
+            return Ok(());
+        };
+
+        let topic = args.trim();
+        if topic.chars().count() > MAX_TOPIC_LENGTH {
+            tx.send(ServerMessage::error(
+                ErrorCode::InvalidArgument,
+                format!("Topic too long (max {} chars)", MAX_TOPIC_LENGTH),
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        let mut channels_lock = state.channels.lock().await;
+        let Some(channel_state) = channels_lock.get_mut(&channel) else {
+            return Ok(());
+        };
+
+        if topic.is_empty() {
+            let message = match &channel_state.topic {
+                Some(topic) => format!("📌 Topic for {}: {}\n", channel, topic),
+                None => format!("📌 No topic is set for {}\n", channel),
+            };
+            drop(channels_lock);
+            tx.send(ServerMessage::reply("topic", message)).await?;
+            return Ok(());
+        }
+
+        let is_operator = role >= Role::Moderator || channel_state.is_operator(client_id);
+        if channel_state.modes.topic_locked && !is_operator {
+            drop(channels_lock);
+            tx.send(ServerMessage::refused(
+                ErrorCode::PermissionDenied,
+                format!(
+                    "Permission denied: the topic of {} is locked to operators",
+                    channel
+                ),
+            ))
+            .await?;
+            return Ok(());
+        }
+
+        channel_state.topic = Some(topic.to_string());
+        drop(channels_lock);
+
+        let notice = format!("📌 {} set the topic of {}: {}\n", nickname, channel, topic);
+        broadcast_to_channel(&state.clients, &channel, &notice).await;
+        Ok(())
+    }
+}
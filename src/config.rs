@@ -0,0 +1,590 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::utils::framing::DEFAULT_MAX_LINE_LENGTH;
+
+/// Config file loaded when no `--config` flag is given, if it exists
+const DEFAULT_CONFIG_PATH: &str = "chat.toml";
+/// Environment variable holding the password that grants admin via /auth
+const ADMIN_PASSWORD_ENV: &str = "CHAT_ADMIN_PASSWORD";
+
+const USAGE: &str = "Usage: tokio-tcp-chat [OPTIONS]
+
+Options:
+  -c, --config <path>              TOML config file (default: chat.toml if present)
+  -b, --bind <addr>                Address to listen on, repeatable (default: 127.0.0.1:8080)
+      --json-bind <addr>           Address speaking the JSON line protocol, repeatable
+      --max-clients <n>            Maximum number of connected clients, 0 for unlimited
+      --outbound-queue-bytes <n>   Bytes of text queued for a slow client
+      --outbound-overflow <policy> drop_oldest or disconnect when that queue is full
+      --output-format <format>     Default output: plain, colored or json
+      --max-nickname-length <n>    Maximum nickname length in characters
+      --max-line-length <n>        Maximum input line length in bytes
+      --motd <text>                Message of the day shown on connect
+      --middlewares <a,b,...>      Comma-separated middleware list, in order
+      --ban-list <path>            File where bans are persisted
+      --history-capacity <n>       Number of public messages kept for /history
+      --history-replay <n>         Number of messages replayed on join
+      --shutdown-notice <text>     Notice sent to clients on shutdown
+      --shutdown-timeout <secs>    Time allowed for clients to drain on shutdown
+      --idle-timeout <secs>        Disconnect idle clients, 0 to disable
+  -h, --help                       Print this help
+
+Settings only available in the config file:
+  admin_password     Password for /auth, also read from CHAT_ADMIN_PASSWORD
+  [flood]            Message and command rate limits
+  [word_filter]      Blocked word list and what happens to matches
+  [automod]          Automatic moderation rules file
+  [sanitize]         Control character handling
+  [repeat_filter]    Repeated message and raid detection
+
+Command-line options override the config file, which overrides the defaults.";
+
+/// Server configuration, loaded from a TOML file and command-line flags
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Addresses to listen on
+    pub bind: Vec<String>,
+    /// Addresses whose clients speak the JSON line protocol
+    pub json_bind: Vec<String>,
+    /// Maximum number of connected clients, 0 for unlimited
+    pub max_clients: usize,
+    /// Bytes of text queued for a client that reads slower than it is sent to
+    pub outbound_queue_bytes: usize,
+    /// What happens when a client's outbound queue is full
+    pub outbound_overflow: OverflowPolicy,
+    /// How messages are rendered for clients that haven't picked a format
+    pub output_format: OutputFormat,
+    /// Maximum nickname length in characters
+    pub max_nickname_length: usize,
+    /// Maximum input line length in bytes
+    pub max_line_length: usize,
+    /// Message of the day shown on connect
+    pub motd: Option<String>,
+    /// Middleware applied to chat messages, in order
+    pub middlewares: Vec<String>,
+    /// File where bans are persisted
+    pub ban_list_path: PathBuf,
+    /// Password that grants the admin role via /auth, disabled when None
+    pub admin_password: Option<String>,
+    /// Number of public messages kept for /history
+    pub history_capacity: usize,
+    /// Number of messages replayed to a client on join
+    pub history_replay: usize,
+    /// Notice sent to every client when the server shuts down
+    pub shutdown_notice: String,
+    /// Seconds to wait for clients to drain during shutdown
+    pub shutdown_timeout: u64,
+    /// Seconds of inactivity before a client is disconnected, 0 to disable
+    pub idle_timeout: u64,
+    /// Rate limits applied by the flood_control middleware and to commands
+    pub flood: FloodConfig,
+    /// Blocked terms applied by the word_filter middleware
+    pub word_filter: WordFilterConfig,
+    /// Rules applied by the automod middleware
+    pub automod: AutomodConfig,
+    /// Control character handling for messages and commands
+    pub sanitize: SanitizeConfig,
+    /// Limits applied by the repeat_filter middleware
+    pub repeat_filter: RepeatFilterConfig,
+}
+
+/// What happens when a client's outbound queue is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OverflowPolicy {
+    /// Discard the oldest queued lines to make room
+    #[default]
+    DropOldest,
+    /// Disconnect the client
+    Disconnect,
+}
+
+/// How messages are rendered for a client, chosen with /format
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Plain,
+    /// Human-readable text with ANSI colors
+    Colored,
+    /// One JSON object per line
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "plain" => Ok(OutputFormat::Plain),
+            "colored" | "color" => Ok(OutputFormat::Colored),
+            "json" => Ok(OutputFormat::Json),
+            other => Err(format!("Unknown output format '{}'", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Plain => write!(f, "plain"),
+            OutputFormat::Colored => write!(f, "colored"),
+            OutputFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// What the word filter does with a message containing a blocked term
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FilterAction {
+    /// Replace the term with asterisks and deliver the message
+    #[default]
+    Mask,
+    /// Reject the whole message
+    Block,
+}
+
+impl std::fmt::Display for FilterAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterAction::Mask => write!(f, "mask"),
+            FilterAction::Block => write!(f, "block"),
+        }
+    }
+}
+
+/// Word filter settings, set in the `[word_filter]` table of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WordFilterConfig {
+    /// File holding the blocked terms, reloadable with /filter reload
+    pub path: PathBuf,
+    /// Whether matching messages are masked or blocked
+    pub action: FilterAction,
+}
+
+impl Default for WordFilterConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("words.txt"),
+            action: FilterAction::default(),
+        }
+    }
+}
+
+/// What happens to control characters and escape sequences in user input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum SanitizeMode {
+    /// Remove them
+    #[default]
+    Strip,
+    /// Replace them with visible `\x1b` style escapes
+    Escape,
+}
+
+/// Sanitizer settings, set in the `[sanitize]` table of the config file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SanitizeConfig {
+    /// Whether control characters are stripped or escaped
+    pub mode: SanitizeMode,
+    /// Keep SGR color and style sequences
+    pub allow_colors: bool,
+}
+
+/// Automod settings, set in the `[automod]` table of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AutomodConfig {
+    /// File holding the `[[rule]]` entries, reloadable with /automod reload
+    pub path: PathBuf,
+}
+
+impl Default for AutomodConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("automod.toml"),
+        }
+    }
+}
+
+/// Repeated message limits, set in the `[repeat_filter]` table of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct RepeatFilterConfig {
+    /// Seconds during which messages are compared
+    pub window: u64,
+    /// Times one client may send the same message within the window
+    pub max_repeats: usize,
+    /// Distinct clients posting the same message within the window that
+    /// count as a raid
+    pub raid_senders: usize,
+    /// Messages shorter than this many letters and digits never count as a raid
+    pub raid_min_length: usize,
+    /// Blocked messages within the window that trigger an automatic mute
+    pub mute_after: u32,
+    /// Length of the automatic mute in seconds
+    pub mute_duration: u64,
+}
+
+impl Default for RepeatFilterConfig {
+    fn default() -> Self {
+        Self {
+            window: 30,
+            max_repeats: 2,
+            raid_senders: 3,
+            raid_min_length: 10,
+            mute_after: 3,
+            mute_duration: 300,
+        }
+    }
+}
+
+/// Token-bucket flood limits, set in the `[flood]` table of the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FloodConfig {
+    /// Chat messages a client can send in a burst
+    pub message_burst: u32,
+    /// Chat messages regained per second
+    pub message_refill_per_sec: f64,
+    /// Commands a client can send in a burst, 0 for no command limit.
+    /// Moderators and admins are never limited.
+    pub command_burst: u32,
+    /// Commands regained per second
+    pub command_refill_per_sec: f64,
+    /// Violations within `violation_window` that trigger an automatic mute
+    pub mute_after: u32,
+    /// Seconds after which earlier violations are forgotten
+    pub violation_window: u64,
+    /// Length of the automatic mute in seconds
+    pub mute_duration: u64,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        Self {
+            message_burst: 5,
+            message_refill_per_sec: 1.0,
+            command_burst: 3,
+            command_refill_per_sec: 0.5,
+            mute_after: 3,
+            violation_window: 60,
+            mute_duration: 300,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: vec!["127.0.0.1:8080".to_string()],
+            json_bind: Vec::new(),
+            max_clients: 1000,
+            outbound_queue_bytes: 64 * 1024,
+            outbound_overflow: OverflowPolicy::default(),
+            output_format: OutputFormat::default(),
+            max_nickname_length: 20,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            motd: None,
+            middlewares: vec![
+                "sanitize".to_string(),
+                "is_muted".to_string(),
+                "flood_control".to_string(),
+                "channel_modes".to_string(),
+                "repeat_filter".to_string(),
+                "word_filter".to_string(),
+                "automod".to_string(),
+            ],
+            ban_list_path: PathBuf::from("bans.txt"),
+            admin_password: None,
+            history_capacity: 500,
+            history_replay: 20,
+            shutdown_notice: "🛑 Server is shutting down. Goodbye!".to_string(),
+            shutdown_timeout: 5,
+            idle_timeout: 0,
+            flood: FloodConfig::default(),
+            word_filter: WordFilterConfig::default(),
+            automod: AutomodConfig::default(),
+            sanitize: SanitizeConfig::default(),
+            repeat_filter: RepeatFilterConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Build the configuration from process arguments, the config file and
+    /// the environment. Returns Ok(None) when only help was requested.
+    pub(crate) fn load() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+            println!("{}", USAGE);
+            return Ok(None);
+        }
+
+        // The config file is read first so flags can override it
+        let config_path = args
+            .iter()
+            .position(|arg| arg == "-c" || arg == "--config")
+            .map(|index| {
+                args.get(index + 1)
+                    .map(PathBuf::from)
+                    .ok_or("Missing value for --config")
+            })
+            .transpose()?;
+
+        let mut config = match &config_path {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => Self::default(),
+        };
+
+        if let Ok(password) = std::env::var(ADMIN_PASSWORD_ENV) {
+            config.admin_password = Some(password);
+        }
+
+        config.apply_args(&args)?;
+        config.validate()?;
+        Ok(Some(config))
+    }
+
+    /// Read a TOML config file
+    fn from_file(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+        let config = toml::from_str(&contents)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        println!("Loaded config from {}", path.display());
+        Ok(config)
+    }
+
+    /// Override values with command-line flags
+    fn apply_args(&mut self, args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut cli_bind = Vec::new();
+        let mut cli_json_bind = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .map(String::as_str)
+                    .ok_or_else(|| format!("Missing value for {}", flag))
+            };
+
+            match flag.as_str() {
+                "-c" | "--config" => {
+                    value()?;
+                }
+                "-b" | "--bind" => cli_bind.push(value()?.to_string()),
+                "--json-bind" => cli_json_bind.push(value()?.to_string()),
+                "--max-clients" => self.max_clients = parse_number(flag, value()?)?,
+                "--outbound-queue-bytes" => {
+                    self.outbound_queue_bytes = parse_number(flag, value()?)?
+                }
+                "--outbound-overflow" => {
+                    self.outbound_overflow = match value()? {
+                        "drop_oldest" => OverflowPolicy::DropOldest,
+                        "disconnect" => OverflowPolicy::Disconnect,
+                        other => {
+                            return Err(format!("Invalid value '{}' for {}", other, flag).into())
+                        }
+                    }
+                }
+                "--output-format" => {
+                    let format = value()?;
+                    self.output_format = format
+                        .parse()
+                        .map_err(|_| format!("Invalid value '{}' for {}", format, flag))?;
+                }
+                "--max-nickname-length" => self.max_nickname_length = parse_number(flag, value()?)?,
+                "--max-line-length" => self.max_line_length = parse_number(flag, value()?)?,
+                "--motd" => self.motd = Some(value()?.to_string()),
+                "--middlewares" => {
+                    self.middlewares = value()?
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "--ban-list" => self.ban_list_path = PathBuf::from(value()?),
+                "--history-capacity" => self.history_capacity = parse_number(flag, value()?)?,
+                "--history-replay" => self.history_replay = parse_number(flag, value()?)?,
+                "--shutdown-notice" => self.shutdown_notice = value()?.to_string(),
+                "--shutdown-timeout" => {
+                    self.shutdown_timeout = parse_number(flag, value()?)? as u64
+                }
+                "--idle-timeout" => self.idle_timeout = parse_number(flag, value()?)? as u64,
+                other => {
+                    return Err(
+                        format!("Unknown option '{}', run with --help for usage", other).into(),
+                    )
+                }
+            }
+        }
+
+        // Bind addresses given on the command line replace the file's list
+        if !cli_bind.is_empty() {
+            self.bind = cli_bind;
+        }
+        if !cli_json_bind.is_empty() {
+            self.json_bind = cli_json_bind;
+        }
+
+        Ok(())
+    }
+
+    /// Reject values the server cannot run with
+    fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.bind.is_empty() && self.json_bind.is_empty() {
+            return Err("At least one bind address is required".into());
+        }
+        if self.outbound_queue_bytes == 0 {
+            return Err("outbound_queue_bytes must be at least 1".into());
+        }
+        if self.max_nickname_length == 0 {
+            return Err("max_nickname_length must be at least 1".into());
+        }
+        if self.max_line_length == 0 {
+            return Err("max_line_length must be at least 1".into());
+        }
+        let flood = &self.flood;
+        if flood.message_burst == 0 {
+            return Err("flood.message_burst must be at least 1".into());
+        }
+        let command_limited = flood.command_burst > 0;
+        if flood.message_refill_per_sec <= 0.0
+            || (command_limited && flood.command_refill_per_sec <= 0.0)
+        {
+            return Err("flood refill rates must be positive".into());
+        }
+        if flood.mute_after == 0 {
+            return Err("flood.mute_after must be at least 1".into());
+        }
+        let repeat_filter = &self.repeat_filter;
+        if repeat_filter.max_repeats == 0 || repeat_filter.mute_after == 0 {
+            return Err("repeat_filter.max_repeats and mute_after must be at least 1".into());
+        }
+        if repeat_filter.raid_senders < 2 {
+            return Err("repeat_filter.raid_senders must be at least 2".into());
+        }
+        Ok(())
+    }
+}
+
+/// Parse a numeric flag value
+fn parse_number(flag: &str, value: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value '{}' for {}", value, flag).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load a config from `contents`, through a file named after `name`
+    fn from_toml(name: &str, contents: &str) -> Config {
+        let path =
+            std::env::temp_dir().join(format!("config-test-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let config = Config::from_file(&path);
+        let _ = std::fs::remove_file(&path);
+        config.unwrap()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn file_overrides_defaults() {
+        let config = from_toml(
+            "file",
+            r#"
+            bind = ["0.0.0.0:9000"]
+            max_clients = 5
+            output_format = "colored"
+
+            [flood]
+            mute_after = 7
+            "#,
+        );
+        let defaults = Config::default();
+        assert_eq!(config.bind, ["0.0.0.0:9000"]);
+        assert_eq!(config.max_clients, 5);
+        assert_eq!(config.output_format, OutputFormat::Colored);
+        assert_eq!(config.flood.mute_after, 7);
+
+        // Everything the file leaves out keeps its default
+        assert_eq!(config.max_line_length, defaults.max_line_length);
+        assert_eq!(config.middlewares, defaults.middlewares);
+        assert_eq!(config.flood.message_burst, defaults.flood.message_burst);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let mut config = from_toml(
+            "flags",
+            r#"
+            bind = ["0.0.0.0:9000", "[::]:9000"]
+            json_bind = ["0.0.0.0:9001"]
+            max_clients = 5
+            motd = "from the file"
+            history_replay = 3
+            "#,
+        );
+        config
+            .apply_args(&args(&[
+                "--config",
+                "ignored.toml",
+                "--bind",
+                "127.0.0.1:7000",
+                "--max-clients",
+                "9",
+                "--motd",
+                "from a flag",
+                "--outbound-overflow",
+                "disconnect",
+            ]))
+            .unwrap();
+
+        // Bind addresses from flags replace the file's list
+        assert_eq!(config.bind, ["127.0.0.1:7000"]);
+        assert_eq!(config.max_clients, 9);
+        assert_eq!(config.motd.as_deref(), Some("from a flag"));
+        assert_eq!(config.outbound_overflow, OverflowPolicy::Disconnect);
+        // Settings no flag touched keep the file's value
+        assert_eq!(config.json_bind, ["0.0.0.0:9001"]);
+        assert_eq!(config.history_replay, 3);
+    }
+
+    #[test]
+    fn invalid_flags_and_files_are_rejected() {
+        let mut config = Config::default();
+        for bad in [
+            &["--max-clients"][..],
+            &["--max-clients", "many"],
+            &["--output-format", "html"],
+            &["--no-such-flag"],
+        ] {
+            assert!(config.apply_args(&args(bad)).is_err(), "{:?}", bad);
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("config-test-unknown-{}.toml", std::process::id()));
+        std::fs::write(&path, "max_client = 5\n").unwrap();
+        let error = Config::from_file(&path).unwrap_err();
+        let _ = std::fs::remove_file(&path);
+        assert!(error.to_string().contains("unknown field"), "{}", error);
+    }
+}
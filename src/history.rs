@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::OutputFormat;
+use crate::outbox::Outbox;
+use crate::server_message::{OutboundMessage, ServerMessage};
+use crate::utils::error::ChatError;
+
+/// A public chat line kept for scrollback
+pub(crate) struct HistoryEntry {
+    pub timestamp: SystemTime,
+    pub channel: String,
+    pub sender_id: u32,
+    pub nickname: String,
+    pub message: String,
+}
+
+impl HistoryEntry {
+    /// Create an entry stamped with the current time
+    pub(crate) fn new(channel: &str, sender_id: u32, nickname: &str, message: &str) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            channel: channel.to_string(),
+            sender_id,
+            nickname: nickname.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    /// The chat message this entry was recorded from, stamped with when it
+    /// was originally sent
+    pub(crate) fn to_message(&self) -> OutboundMessage {
+        OutboundMessage::sent_at(
+            ServerMessage::Chat {
+                channel: self.channel.clone(),
+                sender_id: self.sender_id,
+                sender: self.nickname.clone(),
+                text: self.message.clone(),
+            },
+            self.timestamp,
+        )
+    }
+
+    /// Render as a line for the client, e.g. `[14:03:12] alice: hi`
+    pub(crate) fn render(&self) -> String {
+        format!(
+            "[{}] {}: {}\n",
+            format_timestamp(self.timestamp),
+            self.nickname,
+            self.message
+        )
+    }
+}
+
+/// Bounded ring buffer of recent public messages
+pub(crate) struct History {
+    entries: VecDeque<HistoryEntry>,
+    capacity: usize,
+    replay_count: usize,
+}
+
+impl History {
+    /// Create a history keeping at most `capacity` messages and replaying
+    /// the last `replay_count` of them to newly joined clients
+    pub(crate) fn new(capacity: usize, replay_count: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            replay_count,
+        }
+    }
+
+    /// Record a message, evicting the oldest one when full
+    pub(crate) fn push(&mut self, entry: HistoryEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Get one page of a channel's messages in chronological order, optionally
+    /// filtered by sender nickname. Page 1 holds the most recent `count` messages.
+    pub(crate) fn page(
+        &self,
+        channel: &str,
+        sender: Option<&str>,
+        count: usize,
+        page: usize,
+    ) -> Vec<&HistoryEntry> {
+        let skip = count.saturating_mul(page.saturating_sub(1));
+        let mut entries: Vec<&HistoryEntry> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.channel == channel)
+            .filter(|entry| sender.is_none_or(|sender| entry.nickname.eq_ignore_ascii_case(sender)))
+            .skip(skip)
+            .take(count)
+            .collect();
+        entries.reverse();
+        entries
+    }
+
+    /// Send the messages replayed to a client right after it joins a
+    /// channel, wrapped by `wrap`. Sends nothing when there is nothing to
+    /// replay.
+    pub(crate) async fn send_replay(
+        &self,
+        tx: &Outbox,
+        channel: &str,
+        wrap: impl FnOnce(String) -> ServerMessage,
+    ) -> Result<(), ChatError> {
+        let entries = self.page(channel, None, self.replay_count, 1);
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let header = format!("--- Last {} message(s) in {} ---", entries.len(), channel);
+        send_entries(tx, &entries, header, "--- End of history ---\n", wrap).await
+    }
+}
+
+pub(crate) type SharedHistory = Arc<tokio::sync::Mutex<History>>;
+
+/// Send history entries under `header`. JSON clients get the header on its
+/// own, then one chat message per entry with its original timestamp, sender
+/// and channel. Other clients get a single block of `[HH:MM:SS] nick: text`
+/// lines ending with `footer`. `wrap` turns the header or block into the
+/// message sent.
+pub(crate) async fn send_entries(
+    tx: &Outbox,
+    entries: &[&HistoryEntry],
+    header: String,
+    footer: &str,
+    wrap: impl FnOnce(String) -> ServerMessage,
+) -> Result<(), ChatError> {
+    if tx.format() == OutputFormat::Json {
+        tx.send(wrap(header)).await?;
+        for entry in entries {
+            tx.send(Arc::new(entry.to_message())).await?;
+        }
+        return Ok(());
+    }
+
+    let mut block = format!("{}\n", header);
+    for entry in entries {
+        block.push_str(&entry.render());
+    }
+    block.push_str(footer);
+    tx.send(wrap(block)).await
+}
+
+/// Format a timestamp as `HH:MM:SS` (UTC)
+fn format_timestamp(timestamp: SystemTime) -> String {
+    let secs = timestamp
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
+    format!(
+        "{:02}:{:02}:{:02}",
+        (secs / 3_600) % 24,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
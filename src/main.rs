@@ -1,19 +1,39 @@
+use crate::config::Config;
 use crate::server::Server;
 
+mod automod;
+mod ban_list;
+mod channels;
 mod client;
+mod client_store;
 mod commands;
+mod config;
+mod history;
 mod middlewares;
+mod mutes;
+mod outbox;
+mod presence;
+mod protocol;
 mod server;
+mod server_message;
 mod shared_state;
 mod traits;
 mod utils;
+mod word_list;
 
-const PORT: i16 = 8080;
-const IP: &str = "127.0.0.1";
+#[cfg(feature = "dhat-heap")]
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let server = Server::new(&format!("{}:{}", IP, PORT)).await?;
+    #[cfg(feature = "dhat-heap")]
+    let _profiler = dhat::Profiler::new_heap();
+
+    let Some(config) = Config::load()? else {
+        return Ok(());
+    };
+    let server = Server::new(config).await?;
     server.run().await?;
     Ok(())
 }
@@ -1,5 +1,10 @@
-use crate::shared_state::ClientMap;
+use crate::automod::{notify_moderators, SharedAutomod};
+use crate::channels::SharedChannels;
+use crate::config::Config;
+use crate::mutes::SharedMuteList;
+use crate::shared_state::{ClientMap, ServerState};
 use crate::traits::middleware_trait::{MiddlewareError, MiddlewareTrait};
+use crate::word_list::SharedWordList;
 
 pub(crate) mod moderation;
 
@@ -7,9 +12,91 @@ pub(crate) mod moderation;
 pub(crate) struct MessageContext {
     pub message: String,
     pub sender_id: u32,
-    #[allow(dead_code)]
     pub nickname: String,
+    /// Channel the message is sent to, None for private messages
+    pub channel: Option<String>,
     pub clients: ClientMap,
+    pub channels: SharedChannels,
+    pub mutes: SharedMuteList,
+    pub word_list: SharedWordList,
+    pub automod: SharedAutomod,
+    /// Who receives the message once the chain has run. Middleware that
+    /// restrict delivery set this and let the chain continue.
+    pub delivery: Delivery,
+    /// Notes from middleware about what they did, logged and shown to
+    /// moderators reviewing the message
+    pub annotations: Vec<String>,
+}
+
+/// Where a message goes after the middleware chain accepted it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) enum Delivery {
+    /// To the channel or recipient as usual
+    #[default]
+    Everyone,
+    /// Only appears delivered to the sender (shadow ban)
+    SenderOnly,
+    /// Held back and sent to online moderators for review
+    Moderators { reason: String },
+}
+
+impl MessageContext {
+    /// Create the context for a message from `sender_id` to `channel`, or
+    /// a private message when `channel` is None
+    pub fn new(
+        state: &ServerState,
+        sender_id: u32,
+        nickname: &str,
+        channel: Option<String>,
+        message: &str,
+    ) -> Self {
+        Self {
+            message: message.to_string(),
+            sender_id,
+            nickname: nickname.to_string(),
+            channel,
+            clients: state.clients.clone(),
+            channels: state.channels.clone(),
+            mutes: state.mutes.clone(),
+            word_list: state.word_list.clone(),
+            automod: state.automod.clone(),
+            delivery: Delivery::default(),
+            annotations: Vec::new(),
+        }
+    }
+
+    /// Attach a note to the message without changing it
+    pub fn annotate(&mut self, note: impl Into<String>) {
+        self.annotations.push(note.into());
+    }
+
+    /// Log the annotations gathered by the chain, if any
+    pub fn log_annotations(&self) {
+        if !self.annotations.is_empty() {
+            println!(
+                "Message from {} annotated: {}",
+                self.nickname,
+                self.annotations.join("; ")
+            );
+        }
+    }
+
+    /// Send a held message to the online moderators
+    pub async fn send_for_review(&self, reason: &str) {
+        let mut report = format!(
+            "Held for review from {} in {}: {} ({}",
+            self.nickname,
+            self.channel.as_deref().unwrap_or("a private message"),
+            self.message,
+            reason
+        );
+        for annotation in &self.annotations {
+            report.push_str("; ");
+            report.push_str(annotation);
+        }
+        report.push(')');
+        notify_moderators(&self.clients, "review", &report).await;
+    }
 }
 
 /// Chain of middleware that processes messages sequentially
@@ -29,6 +116,43 @@ impl MiddlewareChain {
         self
     }
 
+    /// Build the chain listed in the config, in the given order. Each
+    /// middleware is created once, so it can keep state across messages.
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        config
+            .middlewares
+            .iter()
+            .enumerate()
+            .try_fold(Self::new(), |chain, (index, name)| {
+                if config.middlewares[..index].contains(name) {
+                    return Err(format!("Middleware '{}' is listed more than once", name));
+                }
+                Ok(chain.add(Self::by_name(name, config)?))
+            })
+    }
+
+    /// Look up a middleware by its configuration name
+    fn by_name(name: &str, config: &Config) -> Result<Box<dyn MiddlewareTrait>, String> {
+        match name {
+            "sanitize" => Ok(Box::new(moderation::SanitizeMiddleware::new(
+                config.sanitize.clone(),
+            ))),
+            "is_muted" => Ok(Box::new(moderation::IsMutedMiddleware)),
+            "flood_control" => Ok(Box::new(moderation::FloodControlMiddleware::new(
+                config.flood.clone(),
+            ))),
+            "channel_modes" => Ok(Box::new(moderation::ChannelModesMiddleware)),
+            "repeat_filter" => Ok(Box::new(moderation::RepeatFilterMiddleware::new(
+                config.repeat_filter.clone(),
+            ))),
+            "word_filter" => Ok(Box::new(moderation::WordFilterMiddleware::new(
+                config.word_filter.action,
+            ))),
+            "automod" => Ok(Box::new(moderation::AutomodMiddleware)),
+            other => Err(format!("Unknown middleware '{}'", other)),
+        }
+    }
+
     /// Process a message through all middleware in the chain
     pub async fn process(&self, ctx: &mut MessageContext) -> Result<(), MiddlewareError> {
         for middleware in &self.middlewares {
@@ -37,9 +161,3 @@ impl MiddlewareChain {
         Ok(())
     }
 }
-
-impl Default for MiddlewareChain {
-    fn default() -> Self {
-        Self::new().add(Box::new(moderation::IsMutedMiddleware))
-    }
-}
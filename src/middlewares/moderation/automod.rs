@@ -0,0 +1,113 @@
+use crate::automod::{mute_duration, notify_moderators, RuleAction};
+use crate::middlewares::{Delivery, MessageContext};
+use crate::mutes::{mute_automatically, Mute};
+use crate::server_message::ServerMessage;
+use crate::shared_state::ClientControl;
+use crate::traits::middleware_trait::{MiddlewareError, MiddlewareTrait};
+use crate::utils::duration::MAX_DURATION;
+use crate::utils::target::ValidatedTarget;
+
+/// Middleware that applies the automod rules file
+pub(crate) struct AutomodMiddleware;
+
+impl MiddlewareTrait for AutomodMiddleware {
+    fn process<'a>(
+        &'a self,
+        ctx: &'a mut MessageContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), MiddlewareError>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let evaluation = ctx
+                .automod
+                .lock()
+                .await
+                .evaluate(&ctx.nickname, &ctx.message);
+
+            let location = ctx
+                .channel
+                .clone()
+                .unwrap_or_else(|| "a private message".to_string());
+            for rule in &evaluation.fired {
+                let report = format!(
+                    "Rule '{}' fired for {} in {}: {}",
+                    rule.name, ctx.nickname, location, rule.action
+                );
+                notify_moderators(&ctx.clients, "automod", &report).await;
+                ctx.annotate(format!("automod rule '{}': {}", rule.name, rule.action));
+            }
+
+            let sender_tx = ctx
+                .clients
+                .get(ctx.sender_id, |client_state| client_state.tx.clone());
+            for rule in &evaluation.fired {
+                if rule.action == RuleAction::Warn {
+                    if let Some(tx) = &sender_tx {
+                        let _ = tx
+                            .send(ServerMessage::system(format!(
+                                "⚠️  Automod warning: {}",
+                                rule.reason
+                            )))
+                            .await;
+                    }
+                }
+            }
+
+            ctx.message = evaluation.message.clone();
+            let Some(rule) = evaluation.stopped_by() else {
+                return Ok(());
+            };
+
+            match &rule.action {
+                RuleAction::Drop => Err(MiddlewareError::Dropped),
+                RuleAction::Shadow => {
+                    ctx.delivery = Delivery::SenderOnly;
+                    Ok(())
+                }
+                RuleAction::Review => {
+                    ctx.delivery = Delivery::Moderators {
+                        reason: rule.reason.clone(),
+                    };
+                    Ok(())
+                }
+                RuleAction::Mute { minutes } => {
+                    // Checked when the rules were loaded
+                    let duration = mute_duration(*minutes).unwrap_or(MAX_DURATION);
+                    let mute = Mute::new(Some(duration), Some(rule.reason.clone()));
+                    let description = mute.describe();
+                    mute_automatically(&ctx.clients, &ctx.mutes, ctx.sender_id, mute).await;
+                    Err(MiddlewareError::Blocked(format!(
+                        "You have been muted{}",
+                        description
+                    )))
+                }
+                RuleAction::Kick => {
+                    let control = ctx
+                        .clients
+                        .get(ctx.sender_id, |client_state| client_state.control.clone());
+                    if let Some(control) = control {
+                        let _ = control
+                            .send(ClientControl::Kick {
+                                reason: Some(format!("automod: {}", rule.reason)),
+                            })
+                            .await;
+                    }
+
+                    let broadcast_msg = format!(
+                        "👢 {} has been kicked automatically: {}\n",
+                        ctx.nickname, rule.reason
+                    );
+                    println!("Broadcasting: {}", broadcast_msg.trim());
+                    let _ = ValidatedTarget::broadcast_to_all(&ctx.clients, &broadcast_msg).await;
+                    Err(MiddlewareError::Blocked(format!(
+                        "Your message was blocked: {}",
+                        rule.reason
+                    )))
+                }
+                _ => Err(MiddlewareError::Blocked(format!(
+                    "Your message was blocked: {}",
+                    rule.reason
+                ))),
+            }
+        })
+    }
+}
@@ -0,0 +1,42 @@
+use crate::middlewares::MessageContext;
+use crate::shared_state::Role;
+use crate::traits::middleware_trait::{MiddlewareError, MiddlewareTrait};
+
+/// Middleware that enforces the modes of the channel a message is sent to
+pub(crate) struct ChannelModesMiddleware;
+
+impl MiddlewareTrait for ChannelModesMiddleware {
+    fn process<'a>(
+        &'a self,
+        ctx: &'a mut MessageContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), MiddlewareError>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            // Private messages are not subject to channel modes
+            let Some(channel) = &ctx.channel else {
+                return Ok(());
+            };
+
+            // Server moderators can always speak
+            let role = ctx
+                .clients
+                .get(ctx.sender_id, |client_state| client_state.role())
+                .unwrap_or_default();
+            if role >= Role::Moderator {
+                return Ok(());
+            }
+
+            let channels_lock = ctx.channels.lock().await;
+            if let Some(channel_state) = channels_lock.get(channel) {
+                if !channel_state.can_speak(ctx.sender_id) {
+                    return Err(MiddlewareError::Blocked(format!(
+                        "{} is moderated, only voiced users can speak",
+                        channel
+                    )));
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
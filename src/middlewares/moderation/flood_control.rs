@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+use crate::config::FloodConfig;
+use crate::middlewares::MessageContext;
+use crate::mutes::{mute_automatically, Mute, SharedMuteList};
+use crate::shared_state::{ClientMap, Role};
+use crate::traits::middleware_trait::{MiddlewareError, MiddlewareTrait};
+use crate::utils::rate_limit::{FloodKind, FloodVerdict, TokenBucket};
+
+/// Middleware that rate limits chat messages with a per-client token bucket
+pub(crate) struct FloodControlMiddleware {
+    config: FloodConfig,
+}
+
+impl FloodControlMiddleware {
+    pub(crate) fn new(config: FloodConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl MiddlewareTrait for FloodControlMiddleware {
+    fn process<'a>(
+        &'a self,
+        ctx: &'a mut MessageContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), MiddlewareError>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            match check_flood(
+                &ctx.clients,
+                &ctx.mutes,
+                ctx.sender_id,
+                FloodKind::Message,
+                &self.config,
+            )
+            .await
+            {
+                Some(notice) => Err(MiddlewareError::Blocked(notice)),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+/// Count one line from a client against its flood limits. Returns the notice
+/// to show the client when the line must be dropped. Repeated message
+/// violations mute the client, keyed on its address like a moderator's
+/// /mute; command violations are only refused. Moderators and admins are
+/// never limited on commands, so they can keep up with a raid.
+pub(crate) async fn check_flood(
+    clients: &ClientMap,
+    mutes: &SharedMuteList,
+    client_id: u32,
+    kind: FloodKind,
+    config: &FloodConfig,
+) -> Option<String> {
+    let (burst, refill_per_sec) = match kind {
+        FloodKind::Message => (config.message_burst, config.message_refill_per_sec),
+        FloodKind::Command => (config.command_burst, config.command_refill_per_sec),
+    };
+
+    // A command burst of 0 turns the command limit off
+    if kind == FloodKind::Command && burst == 0 {
+        return None;
+    }
+
+    let verdict = clients.get_mut(client_id, |client_state| {
+        if kind == FloodKind::Command && client_state.role() >= Role::Moderator {
+            return FloodVerdict::Allowed;
+        }
+        client_state.flood.check(
+            kind,
+            || TokenBucket::new(burst, refill_per_sec),
+            Duration::from_secs(config.violation_window),
+            config.mute_after,
+        )
+    })?;
+
+    match verdict {
+        FloodVerdict::Allowed => None,
+        FloodVerdict::Warned => {
+            Some("You are sending too fast, slow down or you will be muted".to_string())
+        }
+        FloodVerdict::Throttled if kind == FloodKind::Command => {
+            Some("You are sending commands too fast, this one was ignored".to_string())
+        }
+        FloodVerdict::Throttled => {
+            Some("You are sending too fast, this line was dropped".to_string())
+        }
+        FloodVerdict::Mute => {
+            let mute = Mute::new(
+                Some(Duration::from_secs(config.mute_duration)),
+                Some("flooding".to_string()),
+            );
+            let description = mute.describe();
+            mute_automatically(clients, mutes, client_id, mute).await;
+            Some(format!("You have been muted{}", description))
+        }
+    }
+}
@@ -11,14 +11,18 @@ impl MiddlewareTrait for IsMutedMiddleware {
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), MiddlewareError>> + Send + 'a>>
     {
         Box::pin(async move {
-            let clients_lock = ctx.clients.lock().await;
+            let mute = ctx
+                .clients
+                .get(ctx.sender_id, |client_state| {
+                    client_state.active_mute().cloned()
+                })
+                .flatten();
 
-            if let Some(client_state) = clients_lock.get(&ctx.sender_id) {
-                if client_state.is_muted() {
-                    return Err(MiddlewareError::Blocked(
-                        "You are muted and cannot send messages".to_string(),
-                    ));
-                }
+            if let Some(mute) = mute {
+                return Err(MiddlewareError::Blocked(format!(
+                    "You are muted and cannot send messages{}",
+                    mute.describe()
+                )));
             }
 
             Ok(())
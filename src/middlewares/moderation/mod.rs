@@ -1,3 +1,15 @@
+pub(crate) mod automod;
+pub(crate) mod channel_modes;
+pub(crate) mod flood_control;
 pub(crate) mod is_muted;
+pub(crate) mod repeat_filter;
+pub(crate) mod sanitize;
+pub(crate) mod word_filter;
 
+pub(crate) use automod::AutomodMiddleware;
+pub(crate) use channel_modes::ChannelModesMiddleware;
+pub(crate) use flood_control::FloodControlMiddleware;
 pub(crate) use is_muted::IsMutedMiddleware;
+pub(crate) use repeat_filter::RepeatFilterMiddleware;
+pub(crate) use sanitize::SanitizeMiddleware;
+pub(crate) use word_filter::WordFilterMiddleware;
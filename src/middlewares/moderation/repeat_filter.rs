@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use crate::automod::notify_moderators;
+use crate::config::RepeatFilterConfig;
+use crate::middlewares::MessageContext;
+use crate::mutes::{mute_automatically, Mute};
+use crate::traits::middleware_trait::{MiddlewareError, MiddlewareTrait};
+use crate::utils::repeat::{Fingerprint, RecentMessages};
+
+/// Middleware that blocks repeated messages from one client and the same
+/// message posted by several clients, muting clients that keep at it
+pub(crate) struct RepeatFilterMiddleware {
+    config: RepeatFilterConfig,
+    /// Messages seen from every client, for raid detection
+    recent_messages: tokio::sync::Mutex<RecentMessages>,
+}
+
+impl RepeatFilterMiddleware {
+    pub(crate) fn new(config: RepeatFilterConfig) -> Self {
+        Self {
+            config,
+            recent_messages: tokio::sync::Mutex::new(RecentMessages::default()),
+        }
+    }
+}
+
+impl MiddlewareTrait for RepeatFilterMiddleware {
+    fn process<'a>(
+        &'a self,
+        ctx: &'a mut MessageContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), MiddlewareError>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let Some(fingerprint) = Fingerprint::of(&ctx.message) else {
+                return Ok(());
+            };
+            let window = Duration::from_secs(self.config.window);
+
+            let Some(repeats) = ctx.clients.get_mut(ctx.sender_id, |client_state| {
+                client_state.repeats.record(fingerprint, window)
+            }) else {
+                return Ok(());
+            };
+
+            let notice = if repeats > self.config.max_repeats {
+                "You already sent that message, please don't repeat yourself"
+            } else if fingerprint.len >= self.config.raid_min_length {
+                // Short replies like "ok" or "lol" are legitimately repeated
+                // by many users, so only longer messages count as a raid
+                let senders =
+                    self.recent_messages
+                        .lock()
+                        .await
+                        .record(fingerprint, ctx.sender_id, window);
+                if senders < self.config.raid_senders {
+                    return Ok(());
+                }
+                if senders == self.config.raid_senders {
+                    let location = ctx.channel.as_deref().unwrap_or("private messages");
+                    let report = format!(
+                        "Possible raid: {} users posted the same message in {}, latest {}",
+                        senders, location, ctx.nickname
+                    );
+                    notify_moderators(&ctx.clients, "repeat_filter", &report).await;
+                }
+                "This message is being posted by several users and was blocked"
+            } else {
+                return Ok(());
+            };
+
+            let mute = ctx
+                .clients
+                .get_mut(ctx.sender_id, |client_state| {
+                    client_state
+                        .repeats
+                        .violation(window, self.config.mute_after)
+                })
+                .unwrap_or(false);
+            if !mute {
+                return Err(MiddlewareError::Blocked(notice.to_string()));
+            }
+
+            let mute = Mute::new(
+                Some(Duration::from_secs(self.config.mute_duration)),
+                Some("repeated messages".to_string()),
+            );
+            let description = mute.describe();
+            mute_automatically(&ctx.clients, &ctx.mutes, ctx.sender_id, mute).await;
+            Err(MiddlewareError::Blocked(format!(
+                "You have been muted{}",
+                description
+            )))
+        })
+    }
+}
@@ -0,0 +1,41 @@
+use std::borrow::Cow;
+
+use crate::config::SanitizeConfig;
+use crate::middlewares::MessageContext;
+use crate::traits::middleware_trait::{MiddlewareError, MiddlewareTrait};
+use crate::utils::sanitize::sanitize;
+
+/// Middleware that strips or escapes terminal control sequences
+pub(crate) struct SanitizeMiddleware {
+    config: SanitizeConfig,
+}
+
+impl SanitizeMiddleware {
+    pub(crate) fn new(config: SanitizeConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl MiddlewareTrait for SanitizeMiddleware {
+    fn process<'a>(
+        &'a self,
+        ctx: &'a mut MessageContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), MiddlewareError>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let Cow::Owned(sanitized) =
+                sanitize(&ctx.message, self.config.mode, self.config.allow_colors)
+            else {
+                return Ok(());
+            };
+
+            if sanitized.trim().is_empty() {
+                return Err(MiddlewareError::Blocked(
+                    "Your message only contained control characters".to_string(),
+                ));
+            }
+            ctx.message = sanitized;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,47 @@
+use crate::config::FilterAction;
+use crate::middlewares::MessageContext;
+use crate::traits::middleware_trait::{MiddlewareError, MiddlewareTrait};
+
+/// Middleware that masks or blocks terms from the word list
+pub(crate) struct WordFilterMiddleware {
+    action: FilterAction,
+}
+
+impl WordFilterMiddleware {
+    pub(crate) fn new(action: FilterAction) -> Self {
+        Self { action }
+    }
+}
+
+impl MiddlewareTrait for WordFilterMiddleware {
+    fn process<'a>(
+        &'a self,
+        ctx: &'a mut MessageContext,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<(), MiddlewareError>> + Send + 'a>>
+    {
+        Box::pin(async move {
+            let word_list = ctx.word_list.lock().await;
+            let channel = ctx.channel.as_deref();
+
+            match self.action {
+                FilterAction::Block => {
+                    if word_list.contains_match(channel, &ctx.message) {
+                        return Err(MiddlewareError::Blocked(
+                            "Your message contains a blocked word".to_string(),
+                        ));
+                    }
+                }
+                FilterAction::Mask => {
+                    let masked = word_list.mask(channel, &ctx.message);
+                    drop(word_list);
+                    if let Some(masked) = masked {
+                        ctx.message = masked;
+                        ctx.annotate("word_filter masked blocked terms");
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
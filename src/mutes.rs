@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::shared_state::ClientMap;
+use crate::utils::duration::{describe_sanction, expiry_after};
+use crate::utils::target::ValidatedTarget;
+
+/// A mute placed on a user, optionally timed
+#[derive(Debug, Clone)]
+pub(crate) struct Mute {
+    pub expires_at: Option<SystemTime>,
+    pub reason: Option<String>,
+}
+
+impl Mute {
+    /// Create a mute lasting `duration`, or until lifted when None
+    pub(crate) fn new(duration: Option<Duration>, reason: Option<String>) -> Self {
+        Self {
+            expires_at: duration.map(expiry_after),
+            reason,
+        }
+    }
+
+    /// Check if the mute has run out
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= SystemTime::now())
+    }
+
+    /// Time left before the mute expires, None if it lasts until lifted
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        self.expires_at.map(|expires_at| {
+            expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Describe reason and expiry for notices, e.g. ` (spam, expires in 10m)`
+    pub(crate) fn describe(&self) -> String {
+        describe_sanction(self.reason.as_deref(), self.remaining())
+    }
+}
+
+/// A mute recorded for an address, with the nickname it was placed on
+#[derive(Debug, Clone)]
+pub(crate) struct MuteRecord {
+    pub nickname: String,
+    pub mute: Mute,
+}
+
+/// Mutes keyed on the address they were placed from, so a muted user who
+/// reconnects, under any nickname, gets the mute back instead of a fresh,
+/// unmuted client. The nickname is kept for moderators to see.
+#[derive(Debug, Default)]
+pub(crate) struct MuteList {
+    mutes: HashMap<IpAddr, MuteRecord>,
+}
+
+impl MuteList {
+    /// Record a mute for an address, replacing any existing one
+    pub(crate) fn mute(&mut self, ip: IpAddr, nickname: &str, mute: Mute) {
+        let record = MuteRecord {
+            nickname: nickname.to_string(),
+            mute,
+        };
+        self.mutes.insert(ip.to_canonical(), record);
+    }
+
+    /// Remove the mute for an address, returning it if one existed
+    pub(crate) fn unmute(&mut self, ip: IpAddr) -> Option<MuteRecord> {
+        self.mutes.remove(&ip.to_canonical())
+    }
+
+    /// Find an active mute for an address
+    pub(crate) fn find(&self, ip: IpAddr) -> Option<&Mute> {
+        self.mutes
+            .get(&ip.to_canonical())
+            .map(|record| &record.mute)
+            .filter(|mute| !mute.is_expired())
+    }
+
+    /// Drop mutes that have run out
+    pub(crate) fn prune_expired(&mut self) {
+        self.mutes.retain(|_, record| !record.mute.is_expired());
+    }
+}
+
+pub(crate) type SharedMuteList = Arc<tokio::sync::Mutex<MuteList>>;
+
+/// Mute a client on the server's own initiative (flood control, automod),
+/// recorded like a moderator's /mute, and announce it
+pub(crate) async fn mute_automatically(
+    clients: &ClientMap,
+    mutes: &SharedMuteList,
+    client_id: u32,
+    mute: Mute,
+) {
+    let description = mute.describe();
+    let muted = clients.get_mut(client_id, |client_state| {
+        client_state.mute(mute.clone());
+        (client_state.nickname().to_string(), client_state.addr.ip())
+    });
+    let Some((nickname, ip)) = muted else {
+        return;
+    };
+
+    mutes.lock().await.mute(ip, &nickname, mute);
+
+    let broadcast_msg = format!(
+        "🔇 {} has been muted automatically{}.\n",
+        nickname, description
+    );
+    println!("Broadcasting: {}", broadcast_msg.trim());
+    let _ = ValidatedTarget::broadcast_to_all(clients, &broadcast_msg).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn mute_follows_the_address_under_any_nickname() {
+        let mut mutes = MuteList::default();
+        mutes.mute(ip("10.0.0.1"), "Alice", Mute::new(None, None));
+
+        assert!(mutes.find(ip("10.0.0.1")).is_some());
+        assert!(mutes.find(ip("::ffff:10.0.0.1")).is_some());
+        assert!(mutes.find(ip("10.0.0.2")).is_none());
+    }
+
+    #[test]
+    fn unmute_clears_the_address() {
+        let mut mutes = MuteList::default();
+        mutes.mute(ip("10.0.0.1"), "Alice", Mute::new(None, None));
+
+        let record = mutes.unmute(ip("::ffff:10.0.0.1")).unwrap();
+        assert_eq!(record.nickname, "Alice");
+        assert!(mutes.find(ip("10.0.0.1")).is_none());
+        assert!(mutes.unmute(ip("10.0.0.1")).is_none());
+    }
+
+    #[test]
+    fn expired_mutes_are_not_found() {
+        let mut mutes = MuteList::default();
+        mutes.mute(
+            ip("10.0.0.1"),
+            "Alice",
+            Mute::new(Some(Duration::ZERO), None),
+        );
+        assert!(mutes.find(ip("10.0.0.1")).is_none());
+    }
+}
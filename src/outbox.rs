@@ -0,0 +1,390 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde_json::Value;
+use tokio::sync::Notify;
+
+use crate::config::{OutputFormat, OverflowPolicy};
+use crate::server_message::OutboundMessage;
+use crate::utils::error::ChatError;
+
+/// Whether the queue still accepts lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueueState {
+    Open,
+    /// Closed normally; queued lines are still written out
+    Closed,
+    /// Overflowed under the disconnect policy; queued lines are discarded
+    Overflowed,
+}
+
+/// A message waiting to be written, with what it was charged when queued
+struct Queued {
+    message: Arc<OutboundMessage>,
+    /// Request the message answers
+    request_id: Option<Arc<Value>>,
+    size: usize,
+}
+
+struct Queue {
+    /// Messages waiting to be rendered and written
+    lines: VecDeque<Queued>,
+    bytes: usize,
+    state: QueueState,
+    /// How the client's messages are rendered
+    format: OutputFormat,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Wakes the writer when a line is queued or the queue closes
+    readable: Notify,
+    /// Wakes anyone waiting in `Outbox::overflowed`
+    overflowed: Notify,
+    max_bytes: usize,
+    policy: OverflowPolicy,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // Nothing panics while holding the lock, but don't cascade if it does
+        self.queue
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Sending half of a client's outbound queue, cloned wherever something
+/// needs to write to the client
+///
+/// Sending never waits for the client's socket: the queue is bounded in
+/// bytes, and a client that falls behind either loses its oldest lines or
+/// is disconnected, so one slow reader cannot stall anyone else.
+///
+/// Messages are queued as they are and rendered in the client's output
+/// format by the writer task; the queue is charged for the size they will
+/// render to. A broadcast shares one `OutboundMessage` between all its
+/// recipients, which renders each format once, so every client using that
+/// format writes the same text.
+#[derive(Clone)]
+pub(crate) struct Outbox {
+    shared: Arc<Shared>,
+    /// Request that messages sent through this handle answer
+    request_id: Option<Arc<Value>>,
+}
+
+/// Receiving half of a client's outbound queue, owned by its writer task
+pub(crate) struct OutboxReceiver {
+    shared: Arc<Shared>,
+}
+
+/// Create a client's outbound queue holding at most `max_bytes` of text,
+/// rendered in `format` until the client picks another one
+pub(crate) fn outbox(
+    max_bytes: usize,
+    policy: OverflowPolicy,
+    format: OutputFormat,
+) -> (Outbox, OutboxReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            lines: VecDeque::new(),
+            bytes: 0,
+            state: QueueState::Open,
+            format,
+        }),
+        readable: Notify::new(),
+        overflowed: Notify::new(),
+        max_bytes,
+        policy,
+        dropped: AtomicU64::new(0),
+    });
+    (
+        Outbox {
+            shared: Arc::clone(&shared),
+            request_id: None,
+        },
+        OutboxReceiver { shared },
+    )
+}
+
+impl Outbox {
+    /// Queue a line without waiting. Fails once the queue is closed.
+    pub(crate) fn push(&self, message: impl Into<Arc<OutboundMessage>>) -> Result<(), ChatError> {
+        let message = message.into();
+        let shared = &self.shared;
+        // Measure outside the lock. The charge is for the current format, and
+        // is refunded as charged even if the format changes before it is
+        // written.
+        let format = shared.lock().format;
+        let size = message.rendered_len(format, self.request_id.as_deref());
+        let mut queue = shared.lock();
+        if queue.state != QueueState::Open {
+            return Err(ChatError::MessageSendFailed);
+        }
+
+        // A single line larger than the whole queue is still let through
+        // when nothing else is waiting, or it could never be delivered
+        let fits = |queue: &Queue| queue.lines.is_empty() || queue.bytes + size <= shared.max_bytes;
+        if !fits(&queue) {
+            match shared.policy {
+                OverflowPolicy::DropOldest => {
+                    while !fits(&queue) {
+                        if let Some(oldest) = queue.lines.pop_front() {
+                            queue.bytes -= oldest.size;
+                            shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+                OverflowPolicy::Disconnect => {
+                    let discarded = queue.lines.len() as u64 + 1;
+                    queue.lines.clear();
+                    queue.bytes = 0;
+                    queue.state = QueueState::Overflowed;
+                    drop(queue);
+                    shared.dropped.fetch_add(discarded, Ordering::Relaxed);
+                    shared.readable.notify_one();
+                    shared.overflowed.notify_waiters();
+                    return Err(ChatError::MessageSendFailed);
+                }
+            }
+        }
+
+        queue.bytes += size;
+        queue.lines.push_back(Queued {
+            message,
+            request_id: self.request_id.clone(),
+            size,
+        });
+        drop(queue);
+        shared.readable.notify_one();
+        Ok(())
+    }
+
+    /// Queue a line. Never waits for the client; async so it reads like the
+    /// other sends at call sites.
+    pub(crate) async fn send(
+        &self,
+        message: impl Into<Arc<OutboundMessage>>,
+    ) -> Result<(), ChatError> {
+        self.push(message)
+    }
+
+    /// A handle to the same queue whose messages are marked as replies to
+    /// the request `id`, for JSON protocol clients to match them up
+    pub(crate) fn replying_to(&self, id: Option<Value>) -> Outbox {
+        Outbox {
+            shared: Arc::clone(&self.shared),
+            request_id: id.map(Arc::new),
+        }
+    }
+
+    /// Stop accepting lines. Lines already queued are still written out.
+    pub(crate) fn close(&self) {
+        let mut queue = self.shared.lock();
+        if queue.state == QueueState::Open {
+            queue.state = QueueState::Closed;
+        }
+        drop(queue);
+        self.shared.readable.notify_one();
+    }
+
+    /// Resolve once the queue has overflowed under the disconnect policy
+    pub(crate) async fn overflowed(&self) {
+        loop {
+            let notified = self.shared.overflowed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if self.shared.lock().state == QueueState::Overflowed {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Change how the client's messages are rendered from now on, including
+    /// those already queued
+    pub(crate) fn set_format(&self, format: OutputFormat) {
+        self.shared.lock().format = format;
+    }
+
+    /// How the client's messages are rendered
+    pub(crate) fn format(&self) -> OutputFormat {
+        self.shared.lock().format
+    }
+
+    /// Number of lines discarded because the client could not keep up
+    pub(crate) fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl OutboxReceiver {
+    /// Wait for the next message and render it in the client's current
+    /// format. Returns None once the queue is closed and drained, or right
+    /// away after an overflow.
+    pub(crate) async fn recv(&mut self) -> Option<Arc<str>> {
+        loop {
+            let notified = self.shared.readable.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let next = {
+                let mut queue = self.shared.lock();
+                if queue.state == QueueState::Overflowed {
+                    return None;
+                }
+                let next = queue.lines.pop_front();
+                if let Some(queued) = &next {
+                    queue.bytes -= queued.size;
+                } else if queue.state == QueueState::Closed {
+                    return None;
+                }
+                next.map(|queued| (queued, queue.format))
+            };
+            if let Some((queued, format)) = next {
+                // Render outside the lock so senders are never held up by it
+                return Some(match &queued.request_id {
+                    Some(id) => queued.message.render_reply(format, id),
+                    None => queued.message.render(format),
+                });
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for OutboxReceiver {
+    /// The writer is gone, so nothing queued from now on would be written
+    fn drop(&mut self) {
+        let mut queue = self.shared.lock();
+        if queue.state == QueueState::Open {
+            queue.state = QueueState::Closed;
+        }
+        queue.lines.clear();
+        queue.bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_message::ServerMessage;
+
+    /// A line that renders in plain text to `text` and a newline
+    fn line(text: &str) -> ServerMessage {
+        ServerMessage::system(text)
+    }
+
+    fn plain(max_bytes: usize, policy: OverflowPolicy) -> (Outbox, OutboxReceiver) {
+        outbox(max_bytes, policy, OutputFormat::Plain)
+    }
+
+    fn state(tx: &Outbox) -> QueueState {
+        tx.shared.lock().state
+    }
+
+    fn bytes(tx: &Outbox) -> usize {
+        tx.shared.lock().bytes
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_evicts_until_the_new_line_fits() {
+        let (tx, mut rx) = plain(12, OverflowPolicy::DropOldest);
+        for text in ["aa", "bb", "cc"] {
+            tx.push(line(text)).unwrap();
+        }
+        assert_eq!(bytes(&tx), 9);
+
+        // 7 more bytes only fit once the two oldest lines are gone
+        tx.push(line("dddddd")).unwrap();
+        assert_eq!(bytes(&tx), 10);
+        assert_eq!(tx.dropped(), 2);
+        assert_eq!(state(&tx), QueueState::Open);
+
+        assert_eq!(rx.recv().await.as_deref(), Some("cc\n"));
+        assert_eq!(rx.recv().await.as_deref(), Some("dddddd\n"));
+        assert_eq!(bytes(&tx), 0);
+    }
+
+    #[tokio::test]
+    async fn disconnect_closes_the_queue_and_counts_what_it_discards() {
+        let (tx, mut rx) = plain(8, OverflowPolicy::Disconnect);
+        tx.push(line("aaa")).unwrap();
+        tx.push(line("bbb")).unwrap();
+
+        assert!(tx.push(line("c")).is_err());
+        // Both queued lines and the one that did not fit
+        assert_eq!(tx.dropped(), 3);
+        assert_eq!(state(&tx), QueueState::Overflowed);
+        assert_eq!(bytes(&tx), 0);
+        tx.overflowed().await;
+
+        assert!(tx.push(line("d")).is_err());
+        assert_eq!(tx.dropped(), 3);
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn a_line_larger_than_the_budget_goes_through_alone() {
+        let (tx, mut rx) = plain(4, OverflowPolicy::DropOldest);
+        tx.push(line("hello world")).unwrap();
+        assert_eq!(bytes(&tx), 12);
+        assert_eq!(tx.dropped(), 0);
+
+        // It is the oldest line once anything else is queued
+        tx.push(line("x")).unwrap();
+        assert_eq!(tx.dropped(), 1);
+        assert_eq!(rx.recv().await.as_deref(), Some("x\n"));
+
+        let (tx, _rx) = plain(4, OverflowPolicy::Disconnect);
+        tx.push(line("hello world")).unwrap();
+        assert!(tx.push(line("x")).is_err());
+        assert_eq!(tx.dropped(), 2);
+    }
+
+    #[tokio::test]
+    async fn close_still_drains_queued_lines() {
+        let (tx, mut rx) = plain(64, OverflowPolicy::Disconnect);
+        tx.push(line("first")).unwrap();
+        tx.push(line("second")).unwrap();
+        tx.close();
+
+        assert!(tx.push(line("third")).is_err());
+        assert_eq!(state(&tx), QueueState::Closed);
+        assert_eq!(rx.recv().await.as_deref(), Some("first\n"));
+        assert_eq!(rx.recv().await.as_deref(), Some("second\n"));
+        assert_eq!(rx.recv().await, None);
+        // Closing is not an overflow, and loses nothing
+        assert_eq!(state(&tx), QueueState::Closed);
+        assert_eq!(tx.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn queued_lines_are_rendered_in_the_current_format() {
+        let (tx, mut rx) = plain(64, OverflowPolicy::DropOldest);
+        tx.push(line("hello")).unwrap();
+        tx.set_format(OutputFormat::Json);
+
+        let rendered = rx.recv().await.unwrap();
+        let value: Value = serde_json::from_str(&rendered).unwrap();
+        assert_eq!(value["text"], "hello");
+        // Refunded as it was charged, in plain text
+        assert_eq!(bytes(&tx), 0);
+    }
+
+    #[tokio::test]
+    async fn replies_carry_their_request_id() {
+        let (tx, mut rx) = outbox(256, OverflowPolicy::DropOldest, OutputFormat::Json);
+        let reply = tx.replying_to(Some(Value::from(9)));
+        reply.push(line("done")).unwrap();
+        tx.push(line("notice")).unwrap();
+
+        let value: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+        assert_eq!(value["id"], 9);
+        let value: Value = serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+        assert!(value.get("id").is_none());
+        assert_eq!(bytes(&tx), 0);
+    }
+}
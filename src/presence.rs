@@ -0,0 +1,128 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::server_message::{OutboundMessage, ServerMessage};
+use crate::shared_state::ClientMap;
+
+/// Why a client left the chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DisconnectReason {
+    /// Used /quit or closed the connection
+    Quit,
+    /// Removed by a moderator (kick or ban)
+    Kicked,
+    /// Idle for longer than the configured timeout
+    Timeout,
+    /// Fell too far behind reading its messages
+    Overflow,
+    /// The connection failed
+    Error,
+    /// The server is shutting down
+    Shutdown,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::Quit => write!(f, "quit"),
+            DisconnectReason::Kicked => write!(f, "kicked"),
+            DisconnectReason::Timeout => write!(f, "timed out"),
+            DisconnectReason::Overflow => write!(f, "could not keep up"),
+            DisconnectReason::Error => write!(f, "connection error"),
+            DisconnectReason::Shutdown => write!(f, "server shutdown"),
+        }
+    }
+}
+
+impl DisconnectReason {
+    /// Short identifier for machine-readable output
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            DisconnectReason::Quit => "quit",
+            DisconnectReason::Kicked => "kicked",
+            DisconnectReason::Timeout => "timeout",
+            DisconnectReason::Overflow => "overflow",
+            DisconnectReason::Error => "error",
+            DisconnectReason::Shutdown => "shutdown",
+        }
+    }
+}
+
+/// A join, leave or rename event shown to other users
+#[derive(Debug, Clone)]
+pub(crate) enum PresenceEvent {
+    JoinedChannel {
+        nickname: String,
+        channel: String,
+    },
+    PartedChannel {
+        nickname: String,
+        channel: String,
+    },
+    Joined {
+        nickname: String,
+    },
+    Left {
+        nickname: String,
+        reason: DisconnectReason,
+    },
+    Renamed {
+        old_nickname: String,
+        new_nickname: String,
+    },
+}
+
+impl fmt::Display for PresenceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresenceEvent::JoinedChannel { nickname, channel } => {
+                write!(f, "➡️  {} joined {}", nickname, channel)
+            }
+            PresenceEvent::PartedChannel { nickname, channel } => {
+                write!(f, "⬅️  {} left {}", nickname, channel)
+            }
+            PresenceEvent::Joined { nickname } => write!(f, "➡️  {} joined the chat", nickname),
+            PresenceEvent::Left { nickname, reason } => {
+                write!(f, "⬅️  {} left the chat ({})", nickname, reason)
+            }
+            PresenceEvent::Renamed {
+                old_nickname,
+                new_nickname,
+            } => write!(f, "✏️  {} is now known as {}", old_nickname, new_nickname),
+        }
+    }
+}
+
+impl PresenceEvent {
+    /// Channel the event is scoped to, None for server-wide events
+    pub(crate) fn channel(&self) -> Option<&str> {
+        match self {
+            PresenceEvent::JoinedChannel { channel, .. }
+            | PresenceEvent::PartedChannel { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+/// Send a presence event to every client that has announcements enabled,
+/// except the client the event is about. Channel events only reach that
+/// channel's members.
+pub(crate) async fn announce(clients: &ClientMap, subject_id: u32, event: PresenceEvent) {
+    println!("Presence: {}", event);
+
+    let channel = event.channel().map(str::to_string);
+    let message = Arc::new(OutboundMessage::new(ServerMessage::Presence {
+        id: subject_id,
+        event,
+    }));
+    clients.for_each(|id, client_state| {
+        if id != subject_id
+            && client_state.show_presence()
+            && channel
+                .as_ref()
+                .is_none_or(|channel| client_state.channel == *channel)
+        {
+            let _ = client_state.tx.push(Arc::clone(&message));
+        }
+    });
+}
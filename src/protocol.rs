@@ -0,0 +1,242 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::OutputFormat;
+
+/// How a connection talks to the server, fixed by the listener it came in on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Protocol {
+    /// Human-oriented lines: commands start with '/', anything else is chat
+    Text,
+    /// One JSON object per line in both directions, for bots and tests
+    Json,
+}
+
+impl Protocol {
+    /// The output format a new connection starts with
+    pub(crate) fn output_format(self, default: OutputFormat) -> OutputFormat {
+        match self {
+            Protocol::Text => default,
+            Protocol::Json => OutputFormat::Json,
+        }
+    }
+}
+
+/// One line sent by a JSON protocol client
+#[derive(Debug)]
+pub(crate) struct Request {
+    /// Chosen by the client and echoed on every reply to this request
+    pub id: Option<Value>,
+    pub body: RequestBody,
+}
+
+/// What a JSON protocol client asked for
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum RequestBody {
+    /// A chat message to the client's channel. Text starting with '/' is
+    /// sent as is, not run as a command.
+    Message { text: String },
+    /// A command and its arguments, as typed after the '/' in text mode.
+    /// A leading '/' on the name is accepted and dropped.
+    Command {
+        command: String,
+        #[serde(default)]
+        args: String,
+    },
+}
+
+/// Why a JSON protocol line was rejected, with the request id if one could
+/// be read so the error can still be matched to it
+#[derive(Debug)]
+pub(crate) struct RequestError {
+    pub id: Option<Value>,
+    pub reason: String,
+}
+
+impl Request {
+    /// Parse one line of the JSON protocol
+    pub(crate) fn parse(line: &str) -> Result<Self, RequestError> {
+        let mut value: Value = serde_json::from_str(line).map_err(|e| RequestError {
+            id: None,
+            reason: format!("Invalid JSON: {}", e),
+        })?;
+        let id = value.as_object_mut().and_then(|fields| fields.remove("id"));
+        let fail = |reason: String| RequestError {
+            id: id.clone(),
+            reason,
+        };
+
+        if let Some(id) = &id {
+            if !(id.is_string() || id.is_number()) {
+                return Err(fail("Request id must be a string or a number".to_string()));
+            }
+        }
+        let mut body: RequestBody =
+            serde_json::from_value(value).map_err(|e| fail(format!("Invalid request: {}", e)))?;
+
+        // Text mode can never produce a line break inside a line, so neither
+        // may this: it would show up as extra lines for text clients
+        let single_line = |text: &str| !text.contains(['\n', '\r']);
+        match &mut body {
+            RequestBody::Message { text } => {
+                if text.trim().is_empty() {
+                    return Err(fail("Message text cannot be empty".to_string()));
+                }
+                if !single_line(text) {
+                    return Err(fail("Message text must be a single line".to_string()));
+                }
+            }
+            RequestBody::Command { command, args } => {
+                let name = command.strip_prefix('/').unwrap_or(command);
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    return Err(fail(format!("Invalid command name '{}'", command)));
+                }
+                *command = name.to_string();
+                if !single_line(args) {
+                    return Err(fail("Command arguments must be a single line".to_string()));
+                }
+            }
+        }
+        Ok(Request { id, body })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rejected(line: &str) -> RequestError {
+        Request::parse(line).unwrap_err()
+    }
+
+    #[test]
+    fn parses_messages_and_commands() {
+        let request = Request::parse(r#"{"type":"message","text":"hi /all","id":1}"#).unwrap();
+        assert_eq!(request.id, Some(json!(1)));
+        assert!(matches!(request.body, RequestBody::Message { text } if text == "hi /all"));
+
+        let request =
+            Request::parse(r#"{"type":"command","command":"/nick","args":"Bob","id":"a"}"#)
+                .unwrap();
+        assert_eq!(request.id, Some(json!("a")));
+        assert!(matches!(
+            request.body,
+            RequestBody::Command { command, args } if command == "nick" && args == "Bob"
+        ));
+
+        // The id and arguments are optional
+        let request = Request::parse(r#"{"type":"command","command":"help"}"#).unwrap();
+        assert_eq!(request.id, None);
+        assert!(matches!(
+            request.body,
+            RequestBody::Command { command, args } if command == "help" && args.is_empty()
+        ));
+    }
+
+    #[test]
+    fn malformed_json_is_rejected_without_an_id() {
+        for line in [r#"{"type":"message","id":1"#, "hello", ""] {
+            let error = rejected(line);
+            assert_eq!(error.id, None, "{}", line);
+            assert!(
+                error.reason.starts_with("Invalid JSON: "),
+                "{}",
+                error.reason
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_types_and_fields_keep_the_id() {
+        let error = rejected(r#"{"type":"chat","text":"hi","id":7}"#);
+        assert_eq!(error.id, Some(json!(7)));
+        assert!(error
+            .reason
+            .starts_with("Invalid request: unknown variant `chat`"));
+
+        let error = rejected(r#"{"type":"message","text":"hi","to":"Bob","id":8}"#);
+        assert_eq!(error.id, Some(json!(8)));
+        assert!(
+            error.reason.contains("unknown field `to`"),
+            "{}",
+            error.reason
+        );
+
+        // Not an object at all
+        let error = rejected("[1, 2]");
+        assert_eq!(error.id, None);
+        assert!(error.reason.starts_with("Invalid request: "));
+    }
+
+    #[test]
+    fn missing_fields_keep_the_id() {
+        let error = rejected(r#"{"type":"message","id":"m"}"#);
+        assert_eq!(error.id, Some(json!("m")));
+        assert!(
+            error.reason.contains("missing field `text`"),
+            "{}",
+            error.reason
+        );
+
+        let error = rejected(r#"{"text":"hi","id":2}"#);
+        assert_eq!(error.id, Some(json!(2)));
+        assert!(
+            error.reason.contains("missing field `type`"),
+            "{}",
+            error.reason
+        );
+
+        let error = rejected(r#"{"type":"command","id":3}"#);
+        assert_eq!(error.id, Some(json!(3)));
+        assert!(
+            error.reason.contains("missing field `command`"),
+            "{}",
+            error.reason
+        );
+    }
+
+    #[test]
+    fn invalid_values_get_their_own_reasons() {
+        let cases = [
+            (
+                r#"{"type":"message","text":"hi","id":[1]}"#,
+                json!([1]),
+                "Request id must be a string or a number",
+            ),
+            (
+                r#"{"type":"message","text":"  ","id":1}"#,
+                json!(1),
+                "Message text cannot be empty",
+            ),
+            (
+                r#"{"type":"message","text":"a\nb","id":2}"#,
+                json!(2),
+                "Message text must be a single line",
+            ),
+            (
+                r#"{"type":"command","command":"/","id":3}"#,
+                json!(3),
+                "Invalid command name '/'",
+            ),
+            (
+                r#"{"type":"command","command":"ni ck","id":4}"#,
+                json!(4),
+                "Invalid command name 'ni ck'",
+            ),
+            (
+                r#"{"type":"command","command":"nick","args":"a\rb","id":5}"#,
+                json!(5),
+                "Command arguments must be a single line",
+            ),
+        ];
+        for (line, id, reason) in cases {
+            let error = rejected(line);
+            assert_eq!(error.reason, reason, "{}", line);
+            // The id is echoed even when it is the problem
+            assert_eq!(error.id, Some(id), "{}", line);
+        }
+    }
+}
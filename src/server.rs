@@ -1,41 +1,263 @@
-use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use tokio::net::TcpListener;
-use tokio::sync::Mutex;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinSet;
 
+use crate::automod::AutomodRules;
+use crate::ban_list::BanList;
+use crate::channels::Channels;
 use crate::client::Client;
-use crate::shared_state::ClientMap;
+use crate::client_store::ClientStore;
+use crate::config::{Config, OutputFormat};
+use crate::history::History;
+use crate::middlewares::MiddlewareChain;
+use crate::mutes::MuteList;
+use crate::protocol::Protocol;
+use crate::server_message::{ErrorCode, OutboundMessage, ServerMessage};
+use crate::shared_state::{ClientControl, ServerState};
+use crate::word_list::WordList;
+
+/// How often timed mutes are checked for expiry
+const MUTE_EXPIRY_INTERVAL: Duration = Duration::from_secs(1);
 
 pub(crate) struct Server {
-    listener: TcpListener,
-    clients: ClientMap,
+    listeners: Vec<(TcpListener, Protocol)>,
+    state: ServerState,
     client_id_counter: Arc<AtomicU32>,
 }
 
 impl Server {
-    pub(crate) async fn new(addr: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let listener = TcpListener::bind(addr).await?;
-        println!("Server listening on {}", addr);
+    pub(crate) async fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        // Built once so middleware can keep state across messages
+        let middlewares = MiddlewareChain::from_config(&config)?;
+        println!("Middleware chain: {}", config.middlewares.join(" -> "));
+
+        let bans = BanList::load(&config.ban_list_path)?;
+        let word_list = WordList::load(&config.word_filter.path)?;
+        let automod = AutomodRules::load(&config.automod.path)?;
+
+        let mut listeners = Vec::with_capacity(config.bind.len() + config.json_bind.len());
+        for addr in &config.bind {
+            listeners.push((TcpListener::bind(addr).await?, Protocol::Text));
+            println!("Server listening on {}", addr);
+        }
+        for addr in &config.json_bind {
+            listeners.push((TcpListener::bind(addr).await?, Protocol::Json));
+            println!("Server listening on {} (JSON protocol)", addr);
+        }
+
         Ok(Server {
-            listener,
-            clients: Arc::new(Mutex::new(HashMap::new())),
+            listeners,
+            state: ServerState {
+                clients: Arc::new(ClientStore::new()),
+                channels: Arc::new(Mutex::new(Channels::new())),
+                bans: Arc::new(Mutex::new(bans)),
+                mutes: Arc::new(Mutex::new(MuteList::default())),
+                word_list: Arc::new(Mutex::new(word_list)),
+                automod: Arc::new(Mutex::new(automod)),
+                history: Arc::new(Mutex::new(History::new(
+                    config.history_capacity,
+                    config.history_replay,
+                ))),
+                middlewares: Arc::new(middlewares),
+                config: Arc::new(config),
+            },
             client_id_counter: Arc::new(AtomicU32::new(1)),
         })
     }
 
+    /// Accept clients until SIGINT/SIGTERM, then notify and drain them
     pub(crate) async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        // Every client holds a clone; recv() returns None once all are gone
+        let (shutdown_complete_tx, mut shutdown_complete_rx) = mpsc::channel::<()>(1);
+
+        let mut accept_loops = JoinSet::new();
+        for (listener, protocol) in self.listeners {
+            accept_loops.spawn(Self::accept_loop(
+                listener,
+                protocol,
+                self.state.clone(),
+                Arc::clone(&self.client_id_counter),
+                shutdown_complete_tx.clone(),
+            ));
+        }
+
+        let mute_expiry = tokio::spawn(Self::expire_mutes(self.state.clone()));
+
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+
+        // Accept loops only return on error
+        loop {
+            tokio::select! {
+                Some(result) = accept_loops.join_next() => result??,
+                _ = &mut shutdown => break,
+            }
+        }
+
+        println!("Shutdown signal received, no longer accepting connections");
+        accept_loops.shutdown().await;
+        mute_expiry.abort();
+        drop(shutdown_complete_tx);
+
+        let deadline = Duration::from_secs(self.state.config.shutdown_timeout);
+        let drain = async {
+            Self::notify_shutdown(&self.state).await;
+            shutdown_complete_rx.recv().await;
+        };
+        match tokio::time::timeout(deadline, drain).await {
+            Ok(()) => println!("All clients disconnected, server stopped"),
+            Err(_) => {
+                let remaining = self.state.clients.len();
+                eprintln!(
+                    "Shutdown deadline of {}s reached with {} client(s) still connected",
+                    deadline.as_secs(),
+                    remaining
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send the shutdown notice to every client and tell them to disconnect
+    async fn notify_shutdown(state: &ServerState) {
+        let notice = Arc::new(OutboundMessage::new(ServerMessage::system(
+            state.config.shutdown_notice.trim_end(),
+        )));
+        let targets = state.clients.filter_map(|_, client_state| {
+            Some((client_state.tx.clone(), client_state.control.clone()))
+        });
+
+        println!("Notifying {} client(s) of shutdown", targets.len());
+        for (tx, control) in targets {
+            let _ = tx.send(Arc::clone(&notice)).await;
+            let _ = control.send(ClientControl::Shutdown).await;
+        }
+    }
+
+    /// Periodically lift timed mutes that have run out and tell the users
+    async fn expire_mutes(state: ServerState) {
+        let mut interval = tokio::time::interval(MUTE_EXPIRY_INTERVAL);
         loop {
-            let (socket, addr) = self.listener.accept().await?;
-            let client_id = self.client_id_counter.fetch_add(1, Ordering::SeqCst);
+            interval.tick().await;
+            state.mutes.lock().await.prune_expired();
+
+            state.clients.for_each_mut(|id, client_state| {
+                if client_state.lift_expired_mute() {
+                    println!(
+                        "Mute expired for client {} ({})",
+                        id,
+                        client_state.nickname()
+                    );
+                    let _ = client_state.tx.push(ServerMessage::system(
+                        "🔊 Your mute has expired. You can now send messages.",
+                    ));
+                }
+            });
+        }
+    }
+
+    /// Accept connections on one listener until it fails
+    async fn accept_loop(
+        listener: TcpListener,
+        protocol: Protocol,
+        state: ServerState,
+        client_id_counter: Arc<AtomicU32>,
+        shutdown_complete: mpsc::Sender<()>,
+    ) -> std::io::Result<()> {
+        loop {
+            let (socket, addr) = listener.accept().await?;
+
+            // Reject banned addresses before a client is created
+            let ban = state.bans.lock().await.find_ip(addr.ip()).cloned();
+            if let Some(ban) = ban {
+                println!("Rejected banned connection from {}", addr);
+                Self::reject(
+                    socket,
+                    protocol,
+                    ErrorCode::Banned,
+                    format!("You are banned from this server{}", ban.describe()),
+                );
+                continue;
+            }
+
+            let max_clients = state.config.max_clients;
+            if max_clients > 0 && state.clients.len() >= max_clients {
+                println!("Rejected connection from {}: server is full", addr);
+                Self::reject(
+                    socket,
+                    protocol,
+                    ErrorCode::ServerFull,
+                    "Server is full, please try again later".to_string(),
+                );
+                continue;
+            }
+
+            let client_id = client_id_counter.fetch_add(1, Ordering::SeqCst);
 
             println!("New connection from client {}: {}", client_id, addr);
 
-            let client = Client::new(client_id, socket, Arc::clone(&self.clients));
+            let client = Client::new(
+                client_id,
+                socket,
+                addr,
+                protocol,
+                state.clone(),
+                shutdown_complete.clone(),
+            );
 
             tokio::spawn(async move {
                 client.handle().await;
             });
         }
     }
+
+    /// Send a notice to a refused connection and close it
+    fn reject(mut socket: TcpStream, protocol: Protocol, code: ErrorCode, text: String) {
+        let notice: Arc<str> = match protocol {
+            Protocol::Text => format!("⛔ {}\n", text).into(),
+            Protocol::Json => {
+                OutboundMessage::new(ServerMessage::refused(code, text)).render(OutputFormat::Json)
+            }
+        };
+        tokio::spawn(async move {
+            let _ = socket.write_all(notice.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        });
+    }
+}
+
+/// Resolve when the process receives SIGINT (Ctrl-C) or, on Unix, SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                eprintln!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
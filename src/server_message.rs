@@ -0,0 +1,651 @@
+use std::fmt;
+use std::io;
+use std::sync::{Arc, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::config::OutputFormat;
+use crate::presence::PresenceEvent;
+
+const RESET: &str = "\x1b[0m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const MAGENTA: &str = "\x1b[35m";
+const CYAN: &str = "\x1b[36m";
+/// Nickname colors, picked by a hash of the nickname
+const NICKNAME_COLORS: [&str; 6] = [
+    "\x1b[1;31m",
+    "\x1b[1;32m",
+    "\x1b[1;33m",
+    "\x1b[1;34m",
+    "\x1b[1;35m",
+    "\x1b[1;36m",
+];
+
+/// Something the server tells a client. Messages are queued as values and
+/// only rendered to bytes by the client's writer task, in the output format
+/// that client chose, so the same event can go out as plain text, colored
+/// text or JSON.
+#[derive(Debug, Clone)]
+pub(crate) enum ServerMessage {
+    /// A public message in a channel
+    Chat {
+        channel: String,
+        sender_id: u32,
+        sender: String,
+        text: String,
+    },
+    /// A private message, as received or as echoed back to its sender
+    Private {
+        sender_id: u32,
+        sender: String,
+        recipient_id: u32,
+        recipient: String,
+        text: String,
+        /// True for the copy shown to the sender
+        outgoing: bool,
+    },
+    /// Sent first to a JSON protocol client, saying who it is
+    Welcome {
+        id: u32,
+        nickname: String,
+        channel: String,
+    },
+    /// A join, leave or rename of client `id`
+    Presence { id: u32, event: PresenceEvent },
+    /// An announcement or notice from the server
+    System(String),
+    /// The output of a command the client ran
+    CommandReply {
+        command: &'static str,
+        text: String,
+        /// What the command did, for programs; only sent in JSON output
+        data: Option<Value>,
+    },
+    /// A command failed or was used wrongly
+    Error { code: ErrorCode, text: String },
+    /// A message or command was refused by moderation or permissions
+    Refused { code: ErrorCode, text: String },
+    /// An automatic moderation report, only sent to moderators
+    Moderation { source: String, text: String },
+}
+
+/// Why a command or message was not accepted, sent along with errors and
+/// refusals so programs don't have to match on their text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ErrorCode {
+    /// A command was given missing or extra arguments
+    Usage,
+    /// No command has that name
+    UnknownCommand,
+    /// An argument was malformed or not allowed
+    InvalidArgument,
+    /// The user, channel member or ban asked for does not exist
+    NotFound,
+    /// The nickname belongs to another client
+    NicknameTaken,
+    /// The caller's role or channel status does not allow it
+    PermissionDenied,
+    /// Admin login failed or is disabled
+    AuthFailed,
+    /// The client is sending too fast
+    RateLimited,
+    /// A message was blocked by moderation middleware
+    Blocked,
+    /// The connection's address is banned
+    Banned,
+    /// The server has no room for another client
+    ServerFull,
+    /// An input line exceeded the maximum length
+    LineTooLong,
+    /// A JSON protocol request could not be understood
+    InvalidRequest,
+    /// The server failed to carry out a valid request
+    Internal,
+}
+
+impl ErrorCode {
+    /// Stable identifier used in the JSON output
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::Usage => "usage",
+            ErrorCode::UnknownCommand => "unknown_command",
+            ErrorCode::InvalidArgument => "invalid_argument",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::NicknameTaken => "nickname_taken",
+            ErrorCode::PermissionDenied => "permission_denied",
+            ErrorCode::AuthFailed => "auth_failed",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::Blocked => "blocked",
+            ErrorCode::Banned => "banned",
+            ErrorCode::ServerFull => "server_full",
+            ErrorCode::LineTooLong => "line_too_long",
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ServerMessage {
+    /// Plain text, without the trailing newline
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerMessage::Chat { sender, text, .. } => write!(f, "{}: {}", sender, text),
+            ServerMessage::Private {
+                sender,
+                text,
+                outgoing: false,
+                ..
+            } => write!(f, "🔒 [PM from {}] {}", sender, text),
+            ServerMessage::Private {
+                recipient, text, ..
+            } => write!(f, "🔒 [PM to {}] {}", recipient, text),
+            ServerMessage::Welcome {
+                id,
+                nickname,
+                channel,
+            } => write!(f, "👋 You are {} (ID: {}) in {}", nickname, id, channel),
+            ServerMessage::Presence { event, .. } => write!(f, "{}", event),
+            ServerMessage::System(text) => write!(f, "{}", text),
+            ServerMessage::CommandReply { text, .. } => write!(f, "{}", text),
+            ServerMessage::Error { text, .. } => write!(f, "Error: {}", text),
+            ServerMessage::Refused { text, .. } => write!(f, "❌ {}", text),
+            ServerMessage::Moderation { source, text } => write!(f, "🛡️  [{}] {}", source, text),
+        }
+    }
+}
+
+impl ServerMessage {
+    /// Create a command reply; a trailing newline is dropped
+    pub(crate) fn reply(command: &'static str, text: impl Into<String>) -> Self {
+        ServerMessage::CommandReply {
+            command,
+            text: trim_newline(text.into()),
+            data: None,
+        }
+    }
+
+    /// Create a command reply carrying structured `data` for JSON clients;
+    /// a trailing newline is dropped
+    pub(crate) fn reply_with(command: &'static str, text: impl Into<String>, data: Value) -> Self {
+        ServerMessage::CommandReply {
+            command,
+            text: trim_newline(text.into()),
+            data: Some(data),
+        }
+    }
+
+    /// Create a server notice; a trailing newline is dropped
+    pub(crate) fn system(text: impl Into<String>) -> Self {
+        ServerMessage::System(trim_newline(text.into()))
+    }
+
+    /// Create an error; a trailing newline is dropped
+    pub(crate) fn error(code: ErrorCode, text: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code,
+            text: trim_newline(text.into()),
+        }
+    }
+
+    /// Create an error for a command used with the wrong arguments
+    pub(crate) fn usage(text: impl Into<String>) -> Self {
+        ServerMessage::error(ErrorCode::Usage, text)
+    }
+
+    /// Create a refusal
+    pub(crate) fn refused(code: ErrorCode, text: impl Into<String>) -> Self {
+        ServerMessage::Refused {
+            code,
+            text: text.into(),
+        }
+    }
+
+    fn write_colored(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        match self {
+            ServerMessage::Chat { sender, text, .. } => {
+                write!(
+                    out,
+                    "{}{}{}: {}",
+                    nickname_color(sender),
+                    sender,
+                    RESET,
+                    text
+                )
+            }
+            ServerMessage::Private { .. } => write!(out, "{}{}{}", MAGENTA, self, RESET),
+            ServerMessage::Welcome { .. } | ServerMessage::Presence { .. } => {
+                write!(out, "{}{}{}", DIM, self, RESET)
+            }
+            ServerMessage::System(_) => write!(out, "{}{}{}", YELLOW, self, RESET),
+            ServerMessage::CommandReply { .. } => write!(out, "{}", self),
+            ServerMessage::Error { .. } | ServerMessage::Refused { .. } => {
+                write!(out, "{}{}{}", RED, self, RESET)
+            }
+            ServerMessage::Moderation { .. } => write!(out, "{}{}{}", CYAN, self, RESET),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            ServerMessage::Chat {
+                channel,
+                sender_id,
+                sender,
+                text,
+            } => json!({
+                "type": "chat",
+                "channel": channel,
+                "sender_id": sender_id,
+                "sender": sender,
+                "text": text,
+            }),
+            ServerMessage::Private {
+                sender_id,
+                sender,
+                recipient_id,
+                recipient,
+                text,
+                outgoing,
+            } => json!({
+                "type": "private",
+                "sender_id": sender_id,
+                "sender": sender,
+                "recipient_id": recipient_id,
+                "recipient": recipient,
+                "text": text,
+                "outgoing": outgoing,
+            }),
+            ServerMessage::Welcome {
+                id,
+                nickname,
+                channel,
+            } => json!({
+                "type": "welcome",
+                "user_id": id,
+                "nickname": nickname,
+                "channel": channel,
+            }),
+            ServerMessage::Presence { id, event } => presence_json(*id, event),
+            ServerMessage::System(text) => json!({
+                "type": "system",
+                "text": strip_icon(text),
+            }),
+            ServerMessage::CommandReply {
+                command,
+                text,
+                data,
+            } => {
+                let mut value = json!({
+                    "type": "command_reply",
+                    "command": command,
+                    "text": strip_icon(text),
+                });
+                if let Some(data) = data {
+                    value["data"] = data.clone();
+                }
+                value
+            }
+            ServerMessage::Error { code, text } => json!({
+                "type": "error",
+                "code": code.as_str(),
+                "text": text,
+            }),
+            ServerMessage::Refused { code, text } => json!({
+                "type": "refused",
+                "code": code.as_str(),
+                "text": text,
+            }),
+            ServerMessage::Moderation { source, text } => json!({
+                "type": "moderation",
+                "source": source,
+                "text": text,
+            }),
+        }
+    }
+}
+
+/// A message on its way to one or more clients, stamped with when it was
+/// created
+///
+/// Writer tasks render it in their client's format. Each format is rendered
+/// at most once and the result is shared by every recipient, so a broadcast
+/// allocates its text once per format no matter how many clients it goes
+/// to. Its rendered length can be measured without rendering, for queues
+/// to account for it before a writer gets to it.
+#[derive(Debug)]
+pub(crate) struct OutboundMessage {
+    message: ServerMessage,
+    sent_at: SystemTime,
+    /// Rendered plain, colored and JSON output, filled in on first use
+    rendered: [OnceLock<Arc<str>>; 3],
+    /// Length of each rendered output, filled in on first use
+    lengths: [OnceLock<usize>; 3],
+}
+
+/// Counts the bytes written to it, to size output without building it
+#[derive(Default)]
+struct ByteCount(usize);
+
+impl fmt::Write for ByteCount {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+impl io::Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Bytes `value` takes as JSON
+fn json_len(value: &Value) -> usize {
+    let mut count = ByteCount::default();
+    // Writing to a counter cannot fail
+    let _ = serde_json::to_writer(&mut count, value);
+    count.0
+}
+
+fn slot(format: OutputFormat) -> usize {
+    match format {
+        OutputFormat::Plain => 0,
+        OutputFormat::Colored => 1,
+        OutputFormat::Json => 2,
+    }
+}
+
+impl OutboundMessage {
+    pub(crate) fn new(message: ServerMessage) -> Self {
+        Self::sent_at(message, SystemTime::now())
+    }
+
+    /// Wrap a message that was first sent at `sent_at`, e.g. one replayed
+    /// from history
+    pub(crate) fn sent_at(message: ServerMessage, sent_at: SystemTime) -> Self {
+        Self {
+            message,
+            sent_at,
+            rendered: Default::default(),
+            lengths: Default::default(),
+        }
+    }
+
+    /// Render as one line (or block of lines) of output, newline included.
+    /// JSON output also carries when the message was sent.
+    pub(crate) fn render(&self, format: OutputFormat) -> Arc<str> {
+        Arc::clone(
+            self.rendered[slot(format)].get_or_init(|| self.render_uncached(format, None).into()),
+        )
+    }
+
+    /// Length in bytes of what `render` or, with a request id,
+    /// `render_reply` returns, measured without building it
+    pub(crate) fn rendered_len(&self, format: OutputFormat, request_id: Option<&Value>) -> usize {
+        let slot = slot(format);
+        let len = *self.lengths[slot].get_or_init(|| match self.rendered[slot].get() {
+            Some(line) => line.len(),
+            None => self.measure(format),
+        });
+        match (format, request_id) {
+            // The id goes in as one more `,"id":<id>` member
+            (OutputFormat::Json, Some(id)) => len + r#","id":"#.len() + json_len(id),
+            _ => len,
+        }
+    }
+
+    fn measure(&self, format: OutputFormat) -> usize {
+        let mut count = ByteCount::default();
+        // Writing to a counter cannot fail
+        let _ = match format {
+            OutputFormat::Plain => fmt::write(&mut count, format_args!("{}", self.message)),
+            OutputFormat::Colored => self.message.write_colored(&mut count),
+            OutputFormat::Json => {
+                count.0 = json_len(&self.json(None));
+                Ok(())
+            }
+        };
+        count.0 + "\n".len()
+    }
+
+    /// Render as a reply to the request `id`. Only JSON output carries the
+    /// id, so that is the only format rendered separately for the reply.
+    pub(crate) fn render_reply(&self, format: OutputFormat, id: &Value) -> Arc<str> {
+        match format {
+            OutputFormat::Json => self.render_uncached(format, Some(id)).into(),
+            _ => self.render(format),
+        }
+    }
+
+    fn render_uncached(&self, format: OutputFormat, request_id: Option<&Value>) -> String {
+        match format {
+            OutputFormat::Plain => format!("{}\n", self.message),
+            OutputFormat::Colored => {
+                let mut line = String::new();
+                // Writing to a String cannot fail
+                let _ = self.message.write_colored(&mut line);
+                line.push('\n');
+                line
+            }
+            OutputFormat::Json => format!("{}\n", self.json(request_id)),
+        }
+    }
+
+    /// The JSON object sent for this message, with when it was sent and
+    /// the request it answers
+    fn json(&self, request_id: Option<&Value>) -> Value {
+        let mut value = self.message.to_json();
+        let timestamp = self
+            .sent_at
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        value["timestamp"] = json!(timestamp);
+        if let Some(id) = request_id {
+            value["id"] = id.clone();
+        }
+        value
+    }
+}
+
+impl From<ServerMessage> for Arc<OutboundMessage> {
+    fn from(message: ServerMessage) -> Self {
+        Arc::new(OutboundMessage::new(message))
+    }
+}
+
+fn presence_json(id: u32, event: &PresenceEvent) -> Value {
+    match event {
+        PresenceEvent::JoinedChannel { nickname, channel } => json!({
+            "type": "presence",
+            "event": "joined_channel",
+            "user_id": id,
+            "nickname": nickname,
+            "channel": channel,
+        }),
+        PresenceEvent::PartedChannel { nickname, channel } => json!({
+            "type": "presence",
+            "event": "parted_channel",
+            "user_id": id,
+            "nickname": nickname,
+            "channel": channel,
+        }),
+        PresenceEvent::Joined { nickname } => json!({
+            "type": "presence",
+            "event": "joined",
+            "user_id": id,
+            "nickname": nickname,
+        }),
+        PresenceEvent::Left { nickname, reason } => json!({
+            "type": "presence",
+            "event": "left",
+            "user_id": id,
+            "nickname": nickname,
+            "reason": reason.code(),
+        }),
+        PresenceEvent::Renamed {
+            old_nickname,
+            new_nickname,
+        } => json!({
+            "type": "presence",
+            "event": "renamed",
+            "user_id": id,
+            "old_nickname": old_nickname,
+            "nickname": new_nickname,
+        }),
+    }
+}
+
+fn trim_newline(mut text: String) -> String {
+    while text.ends_with('\n') {
+        text.pop();
+    }
+    text
+}
+
+/// Drop a leading emoji marker such as "✅ " from human-oriented text
+fn strip_icon(text: &str) -> &str {
+    match text.split_once(' ') {
+        Some((first, rest)) if !first.is_ascii() && !first.chars().any(char::is_alphanumeric) => {
+            rest.trim_start()
+        }
+        _ => text,
+    }
+}
+
+fn nickname_color(nickname: &str) -> &'static str {
+    let hash = nickname.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as usize)
+    });
+    NICKNAME_COLORS[hash % NICKNAME_COLORS.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn sent(message: ServerMessage) -> OutboundMessage {
+        OutboundMessage::sent_at(message, UNIX_EPOCH + Duration::from_millis(1_500))
+    }
+
+    fn chat() -> ServerMessage {
+        ServerMessage::Chat {
+            channel: "#general".to_string(),
+            sender_id: 7,
+            sender: "Alice".to_string(),
+            text: "hello".to_string(),
+        }
+    }
+
+    #[test]
+    fn plain_is_the_display_text_on_one_line() {
+        assert_eq!(&*sent(chat()).render(OutputFormat::Plain), "Alice: hello\n");
+        assert_eq!(
+            &*sent(ServerMessage::error(ErrorCode::NotFound, "No such user\n"))
+                .render(OutputFormat::Plain),
+            "Error: No such user\n"
+        );
+    }
+
+    #[test]
+    fn colored_wraps_the_text_in_its_color() {
+        assert_eq!(
+            &*sent(chat()).render(OutputFormat::Colored),
+            format!("{}Alice{}: hello\n", nickname_color("Alice"), RESET)
+        );
+        assert_eq!(
+            &*sent(ServerMessage::system("Restarting")).render(OutputFormat::Colored),
+            format!("{}Restarting{}\n", YELLOW, RESET)
+        );
+        // Replies are left in the terminal's own color
+        assert_eq!(
+            &*sent(ServerMessage::reply("who", "2 users")).render(OutputFormat::Colored),
+            "2 users\n"
+        );
+    }
+
+    #[test]
+    fn json_carries_the_fields_and_timestamp() {
+        let line = sent(chat()).render(OutputFormat::Json);
+        assert!(line.ends_with('\n'));
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "type": "chat",
+                "channel": "#general",
+                "sender_id": 7,
+                "sender": "Alice",
+                "text": "hello",
+                "timestamp": 1_500,
+            })
+        );
+    }
+
+    #[test]
+    fn json_replies_carry_data_and_the_request_id() {
+        let message = sent(ServerMessage::reply_with(
+            "mute",
+            "✅ Muted Bob",
+            json!({ "nickname": "Bob" }),
+        ));
+        let value: Value =
+            serde_json::from_str(&message.render_reply(OutputFormat::Json, &json!(3))).unwrap();
+        assert_eq!(value["text"], "Muted Bob");
+        assert_eq!(value["data"], json!({ "nickname": "Bob" }));
+        assert_eq!(value["id"], 3);
+        // The id is not kept for the next recipient
+        let value: Value = serde_json::from_str(&message.render(OutputFormat::Json)).unwrap();
+        assert!(value.get("id").is_none());
+    }
+
+    #[test]
+    fn rendered_len_matches_what_is_rendered() {
+        let messages = [
+            chat(),
+            ServerMessage::system("📢 Restarting"),
+            ServerMessage::reply_with("who", "✅ 2 users", json!({ "count": 2 })),
+            ServerMessage::refused(ErrorCode::Blocked, "Watch your language"),
+        ];
+        let ids = [json!(1), json!("request-é"), json!({ "n": [1, 2] })];
+        for message in messages {
+            for format in [
+                OutputFormat::Plain,
+                OutputFormat::Colored,
+                OutputFormat::Json,
+            ] {
+                // Measured both before and after the render is cached
+                let message = sent(message.clone());
+                let measured = message.rendered_len(format, None);
+                assert_eq!(measured, message.render(format).len());
+                assert_eq!(message.rendered_len(format, None), measured);
+                for id in &ids {
+                    assert_eq!(
+                        message.rendered_len(format, Some(id)),
+                        message.render_reply(format, id).len()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strip_icon_drops_only_a_leading_symbol() {
+        assert_eq!(strip_icon("✅ Muted Bob"), "Muted Bob");
+        assert_eq!(strip_icon("🛡️  Two spaces"), "Two spaces");
+        assert_eq!(strip_icon("Muted Bob"), "Muted Bob");
+        assert_eq!(strip_icon("Café is open"), "Café is open");
+        assert_eq!(strip_icon("✅"), "✅");
+        assert_eq!(strip_icon(""), "");
+    }
+}
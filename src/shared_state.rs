@@ -1,56 +1,245 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{fmt, net::SocketAddr, str::FromStr, sync::Arc};
 use tokio::sync::mpsc;
 
+use crate::automod::SharedAutomod;
+use crate::ban_list::SharedBanList;
+use crate::channels::{SharedChannels, DEFAULT_CHANNEL};
+use crate::client_store::{ClientStore, Nicknamed};
+use crate::config::Config;
+use crate::history::SharedHistory;
+use crate::middlewares::MiddlewareChain;
+use crate::mutes::{Mute, SharedMuteList};
+use crate::outbox::Outbox;
+use crate::protocol::Protocol;
+use crate::server_message::ServerMessage;
+use crate::utils::error::ChatError;
+use crate::utils::rate_limit::FloodState;
+use crate::utils::repeat::RepeatState;
+use crate::word_list::SharedWordList;
+
+/// Out-of-band signals delivered to a client's connection task
+#[derive(Debug)]
+pub(crate) enum ClientControl {
+    /// Forcibly disconnect the client, with an optional reason
+    Kick { reason: Option<String> },
+    /// Disconnect the client on the server's own account, telling it why
+    Disconnect { notice: String },
+    /// The server is shutting down, stop reading and disconnect
+    Shutdown,
+}
+
+/// Permission level of a client, ordered from least to most privileged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Role {
+    #[default]
+    User,
+    Moderator,
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::User => write!(f, "user"),
+            Role::Moderator => write!(f, "moderator"),
+            Role::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.trim().to_ascii_lowercase().as_str() {
+            "user" => Ok(Role::User),
+            "mod" | "moderator" => Ok(Role::Moderator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Unknown role '{}'", other)),
+        }
+    }
+}
+
 /// Shared state for a connected client
 pub(crate) struct SharedClientState {
-    pub nickname: String,
-    pub tx: mpsc::Sender<String>,
-    is_muted: bool,
+    nickname: String,
+    pub tx: Outbox,
+    pub control: mpsc::Sender<ClientControl>,
+    pub addr: SocketAddr,
+    /// How the client talks to the server
+    pub protocol: Protocol,
+    /// Channel the client is currently chatting in
+    pub channel: String,
+    /// Rate limiting state for messages and commands
+    pub flood: FloodState,
+    /// Recently sent messages, for repeat detection
+    pub repeats: RepeatState,
+    /// Wrong passwords given to /auth on this connection
+    pub failed_auths: u32,
+    role: Role,
+    mute: Option<Mute>,
+    show_presence: bool,
 }
 
 impl SharedClientState {
     /// Create a new client state
-    pub fn new(nickname: String, tx: mpsc::Sender<String>) -> Self {
+    pub fn new(
+        nickname: String,
+        tx: Outbox,
+        control: mpsc::Sender<ClientControl>,
+        addr: SocketAddr,
+        protocol: Protocol,
+    ) -> Self {
         Self {
             nickname,
             tx,
-            is_muted: false,
+            control,
+            addr,
+            protocol,
+            channel: DEFAULT_CHANNEL.to_string(),
+            flood: FloodState::default(),
+            repeats: RepeatState::default(),
+            failed_auths: 0,
+            role: Role::User,
+            mute: None,
+            show_presence: true,
         }
     }
 
-    /// Check if the client is muted
-    pub fn is_muted(&self) -> bool {
-        self.is_muted
+    /// Get the client's nickname
+    pub fn nickname(&self) -> &str {
+        &self.nickname
+    }
+
+    /// Get the client's role
+    pub fn role(&self) -> Role {
+        self.role
     }
 
-    /// Mute the client. Returns true if state changed.
-    pub fn mute(&mut self) -> bool {
-        if !self.is_muted {
-            self.is_muted = true;
+    /// Change the client's role. Returns true if state changed.
+    pub fn set_role(&mut self, role: Role) -> bool {
+        if self.role != role {
+            self.role = role;
             true
         } else {
             false
         }
     }
 
+    /// Check if the client is muted. Expired mutes no longer count, even
+    /// before the expiry task has lifted them.
+    pub fn is_muted(&self) -> bool {
+        self.active_mute().is_some()
+    }
+
+    /// Get the client's mute, if one is in effect
+    pub fn active_mute(&self) -> Option<&Mute> {
+        self.mute.as_ref().filter(|mute| !mute.is_expired())
+    }
+
+    /// Mute the client, replacing any existing mute. Returns true if the
+    /// client was not muted before.
+    pub fn mute(&mut self, mute: Mute) -> bool {
+        let was_muted = self.is_muted();
+        self.mute = Some(mute);
+        !was_muted
+    }
+
     /// Unmute the client. Returns true if state changed.
     pub fn unmute(&mut self) -> bool {
-        if self.is_muted {
-            self.is_muted = false;
+        let was_muted = self.is_muted();
+        self.mute = None;
+        was_muted
+    }
+
+    /// Remove the mute if it has expired. Returns true if one was lifted.
+    pub fn lift_expired_mute(&mut self) -> bool {
+        if self.mute.as_ref().is_some_and(Mute::is_expired) {
+            self.mute = None;
             true
         } else {
             false
         }
     }
 
+    /// Check if the client wants join, leave and rename announcements
+    pub fn show_presence(&self) -> bool {
+        self.show_presence
+    }
+
+    /// Enable or disable presence announcements for this client
+    pub fn set_show_presence(&mut self, show: bool) {
+        self.show_presence = show;
+    }
+
     /// Send a message to this client
     #[allow(dead_code)]
-    pub async fn send(
-        &self,
-        message: impl Into<String>,
-    ) -> Result<(), mpsc::error::SendError<String>> {
-        self.tx.send(message.into()).await
+    pub async fn send(&self, message: ServerMessage) -> Result<(), ChatError> {
+        self.tx.send(message).await
     }
 }
 
-pub(crate) type ClientMap = Arc<tokio::sync::Mutex<HashMap<u32, SharedClientState>>>;
+/// Only `ClientStore` sets the nickname, so its index stays in sync; use
+/// `ClientStore::rename` elsewhere
+impl Nicknamed for SharedClientState {
+    fn nickname(&self) -> &str {
+        &self.nickname
+    }
+
+    fn set_nickname(&mut self, nickname: String) {
+        self.nickname = nickname;
+    }
+}
+
+pub(crate) type ClientMap = Arc<ClientStore<SharedClientState>>;
+
+/// Server-wide state shared by every connection
+#[derive(Clone)]
+pub(crate) struct ServerState {
+    pub clients: ClientMap,
+    pub channels: SharedChannels,
+    pub bans: SharedBanList,
+    pub mutes: SharedMuteList,
+    pub word_list: SharedWordList,
+    pub automod: SharedAutomod,
+    pub history: SharedHistory,
+    /// Middleware applied to chat messages, shared by every client
+    pub middlewares: Arc<MiddlewareChain>,
+    pub config: Arc<Config>,
+}
+
+#[cfg(test)]
+impl ServerState {
+    /// State for tests: the default config, no middleware and empty lists,
+    /// with every file path pointing nowhere
+    pub(crate) fn for_tests() -> Self {
+        use crate::automod::AutomodRules;
+        use crate::ban_list::BanList;
+        use crate::channels::Channels;
+        use crate::history::History;
+        use crate::mutes::MuteList;
+        use crate::word_list::WordList;
+        use tokio::sync::Mutex;
+
+        let missing = std::path::Path::new("/nonexistent/tokio-tcp-chat");
+        let config = Config::default();
+        ServerState {
+            clients: Arc::new(ClientStore::new()),
+            channels: Arc::new(Mutex::new(Channels::new())),
+            bans: Arc::new(Mutex::new(BanList::load(missing.join("bans.txt")).unwrap())),
+            mutes: Arc::new(Mutex::new(MuteList::default())),
+            word_list: Arc::new(Mutex::new(
+                WordList::load(missing.join("words.txt")).unwrap(),
+            )),
+            automod: Arc::new(Mutex::new(
+                AutomodRules::load(missing.join("automod.toml")).unwrap(),
+            )),
+            history: Arc::new(Mutex::new(History::new(
+                config.history_capacity,
+                config.history_replay,
+            ))),
+            middlewares: Arc::new(MiddlewareChain::new()),
+            config: Arc::new(config),
+        }
+    }
+}
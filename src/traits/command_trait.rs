@@ -1,24 +1,32 @@
-use tokio::sync::mpsc::Sender;
+use crate::outbox::Outbox;
 
-use crate::{shared_state::ClientMap, utils::error::BoxError};
+use crate::{
+    shared_state::{Role, ServerState},
+    utils::error::BoxError,
+};
 
 /// Trait that all commands must implement
 ///
 /// Commands are executed in response to client messages and can perform
 /// actions such as changing nicknames, sending messages to specific clients,
 /// or modifying client state. Each command has access to the client's
-/// sender channel, nickname, arguments, shared client map, and client ID.
+/// sender channel, nickname, arguments, shared server state, and client ID.
+/// Commands may require a minimum role, checked before they execute.
 pub(crate) trait CommandTrait {
     /// Creates a new instance of the command.
     #[allow(unused)]
     fn new() -> Self;
+    /// Minimum role a client needs to run the command.
+    fn required_role(&self) -> Role {
+        Role::User
+    }
     /// Executes the command.
     async fn execute(
         &self,
-        tx: &Sender<String>,
+        tx: &Outbox,
         nickname: &mut String,
         args: &str,
-        clients: &ClientMap,
+        state: &ServerState,
         client_id: u32,
     ) -> Result<(), BoxError>;
 }
@@ -1,4 +1,5 @@
 use crate::middlewares::MessageContext;
+use crate::server_message::ErrorCode;
 use std::future::Future;
 use std::pin::Pin;
 
@@ -22,6 +23,18 @@ pub(crate) enum MiddlewareError {
     Blocked(String),
     /// Message failed validation
     ValidationFailed(String),
+    /// Message is discarded without telling the sender
+    Dropped,
+}
+
+impl MiddlewareError {
+    /// Error code reported to the sender
+    pub(crate) fn code(&self) -> ErrorCode {
+        match self {
+            MiddlewareError::ValidationFailed(_) => ErrorCode::InvalidArgument,
+            _ => ErrorCode::Blocked,
+        }
+    }
 }
 
 impl std::fmt::Display for MiddlewareError {
@@ -29,6 +42,7 @@ impl std::fmt::Display for MiddlewareError {
         match self {
             MiddlewareError::Blocked(msg) => write!(f, "{}", msg),
             MiddlewareError::ValidationFailed(msg) => write!(f, "{}", msg),
+            MiddlewareError::Dropped => write!(f, "Message dropped"),
         }
     }
 }
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+/// Longest mute or ban that can be given, about ten years
+pub(crate) const MAX_DURATION: Duration = Duration::from_secs(3650 * 24 * 60 * 60);
+
+/// Why a duration could not be parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DurationError {
+    /// Not a duration at all
+    Invalid,
+    /// A duration, but longer than `MAX_DURATION`
+    TooLong,
+}
+
+impl fmt::Display for DurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DurationError::Invalid => write!(f, "Invalid duration"),
+            DurationError::TooLong => write!(
+                f,
+                "Duration too long (max {})",
+                format_duration(MAX_DURATION)
+            ),
+        }
+    }
+}
+
+/// Parse a human duration such as `30s`, `10m`, `2h`, `1d` or `1h30m`
+pub(crate) fn parse_duration(input: &str) -> Result<Duration, DurationError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(DurationError::Invalid);
+    }
+
+    let mut total: u64 = 0;
+    let mut digits = String::new();
+    for c in input.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        if digits.is_empty() {
+            return Err(DurationError::Invalid);
+        }
+        // Only digits are left, so failing to parse means too many of them
+        let value: u64 = digits.parse().map_err(|_| DurationError::TooLong)?;
+        digits.clear();
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => return Err(DurationError::Invalid),
+        };
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or(DurationError::TooLong)?;
+    }
+
+    // Every number must carry a unit
+    if !digits.is_empty() || total == 0 {
+        return Err(DurationError::Invalid);
+    }
+
+    let duration = Duration::from_secs(total);
+    if duration > MAX_DURATION {
+        return Err(DurationError::TooLong);
+    }
+    Ok(duration)
+}
+
+/// When something lasting `duration` from now runs out. Durations are
+/// capped at `MAX_DURATION`, so this cannot overflow.
+pub(crate) fn expiry_after(duration: Duration) -> SystemTime {
+    SystemTime::now() + duration.min(MAX_DURATION)
+}
+
+/// Format a duration as a short human string, e.g. `1h 5m` or `42s`
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, minutes, seconds) = (
+        secs / 86_400,
+        (secs % 86_400) / 3_600,
+        (secs % 3_600) / 60,
+        secs % 60,
+    );
+
+    let parts: Vec<String> = [(days, "d"), (hours, "h"), (minutes, "m"), (seconds, "s")]
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+
+    if parts.is_empty() {
+        "0s".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Split command arguments of the form `[duration] [reason]`, where the
+/// first word is only taken as a duration if it parses as one. A duration
+/// that is too long is an error rather than part of the reason.
+pub(crate) fn parse_duration_and_reason(
+    args: &str,
+) -> Result<(Option<Duration>, Option<String>), DurationError> {
+    let args = args.trim();
+    let (first, remainder) = args.split_once(' ').unwrap_or((args, ""));
+    let (duration, reason) = match parse_duration(first) {
+        Ok(duration) => (Some(duration), remainder.trim()),
+        Err(DurationError::Invalid) => (None, args),
+        Err(DurationError::TooLong) => return Err(DurationError::TooLong),
+    };
+    Ok((duration, (!reason.is_empty()).then(|| reason.to_string())))
+}
+
+/// Describe a sanction's reason and time left for notices, e.g.
+/// ` (spam, expires in 2h)` or ` (permanent)`
+pub(crate) fn describe_sanction(reason: Option<&str>, remaining: Option<Duration>) -> String {
+    let expiry = match remaining {
+        // Round up so a sanction never claims to expire in 0s
+        Some(remaining) => format!(
+            "expires in {}",
+            format_duration(Duration::from_secs(remaining.as_secs_f64().ceil() as u64))
+        ),
+        None => "permanent".to_string(),
+    };
+    match reason {
+        Some(reason) => format!(" ({}, {})", reason, expiry),
+        None => format!(" ({})", expiry),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_units_and_combinations() {
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1h30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2W"), Ok(Duration::from_secs(14 * 86_400)));
+    }
+
+    #[test]
+    fn rejects_missing_or_unknown_units() {
+        assert_eq!(parse_duration("10"), Err(DurationError::Invalid));
+        assert_eq!(parse_duration("1h30"), Err(DurationError::Invalid));
+        assert_eq!(parse_duration("m"), Err(DurationError::Invalid));
+        assert_eq!(parse_duration("10x"), Err(DurationError::Invalid));
+        assert_eq!(parse_duration("0s"), Err(DurationError::Invalid));
+        assert_eq!(parse_duration(""), Err(DurationError::Invalid));
+    }
+
+    #[test]
+    fn rejects_overflow_and_durations_past_the_cap() {
+        assert_eq!(
+            parse_duration("10000000000000000000s"),
+            Err(DurationError::TooLong)
+        );
+        assert_eq!(
+            parse_duration("99999999999999999999999s"),
+            Err(DurationError::TooLong)
+        );
+        assert_eq!(
+            parse_duration("3000000000000000w"),
+            Err(DurationError::TooLong)
+        );
+        assert_eq!(parse_duration("3651d"), Err(DurationError::TooLong));
+        assert_eq!(parse_duration("3650d"), Ok(MAX_DURATION));
+    }
+
+    #[test]
+    fn splits_duration_from_reason() {
+        assert_eq!(
+            parse_duration_and_reason("10m spamming links"),
+            Ok((
+                Some(Duration::from_secs(600)),
+                Some("spamming links".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_duration_and_reason("spamming links"),
+            Ok((None, Some("spamming links".to_string())))
+        );
+        assert_eq!(parse_duration_and_reason(""), Ok((None, None)));
+        assert_eq!(
+            parse_duration_and_reason("10000000000000000000s spam"),
+            Err(DurationError::TooLong)
+        );
+    }
+
+    #[test]
+    fn expiry_is_clamped_to_the_cap() {
+        let latest = SystemTime::now() + MAX_DURATION;
+        assert!(expiry_after(Duration::MAX) <= latest + Duration::from_secs(1));
+    }
+}
@@ -1,5 +1,4 @@
 use std::fmt;
-use tokio::sync::mpsc;
 
 /// Custom error type for chat operations
 #[derive(Debug)]
@@ -24,6 +23,14 @@ pub enum ChatError {
     Muted,
     /// Target cannot be empty
     TargetEmpty,
+    /// Input line exceeded the maximum allowed length
+    LineTooLong {
+        max: usize,
+    },
+    /// Channel name validation failed
+    InvalidChannelName(String),
+    /// Joining a channel was refused by its modes
+    ChannelAccessDenied(String),
 }
 
 impl fmt::Display for ChatError {
@@ -43,18 +50,19 @@ impl fmt::Display for ChatError {
             ChatError::ValidationFailed(reason) => write!(f, "Validation failed: {}", reason),
             ChatError::Muted => write!(f, "You are muted and cannot send messages"),
             ChatError::TargetEmpty => write!(f, "Target cannot be empty"),
+            ChatError::LineTooLong { max } => {
+                write!(f, "Message too long (max {} bytes), it was discarded", max)
+            }
+            ChatError::InvalidChannelName(reason) => {
+                write!(f, "Invalid channel name: {}", reason)
+            }
+            ChatError::ChannelAccessDenied(reason) => write!(f, "Cannot join: {}", reason),
         }
     }
 }
 
 impl std::error::Error for ChatError {}
 
-impl From<mpsc::error::SendError<String>> for ChatError {
-    fn from(_: mpsc::error::SendError<String>) -> Self {
-        ChatError::MessageSendFailed
-    }
-}
-
 // Type aliases for convenience
 pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
 #[allow(dead_code)]
@@ -0,0 +1,158 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Default maximum length of a single line, in bytes (excluding the newline)
+pub(crate) const DEFAULT_MAX_LINE_LENGTH: usize = 4096;
+
+/// A single frame read from a client connection
+pub(crate) enum Frame {
+    /// One complete logical line, without its line terminator
+    Line(String),
+    /// A line exceeded the maximum length and was discarded
+    TooLong,
+}
+
+/// Splits a byte stream into newline-delimited lines
+///
+/// Bytes are buffered across reads, so a line split over several TCP segments
+/// is reassembled and several lines arriving in one segment are yielded one
+/// at a time. Lines longer than `max_line_length` are dropped up to the next
+/// newline and reported once as `Frame::TooLong`.
+pub(crate) struct LineReader<R> {
+    reader: R,
+    buffer: Vec<u8>,
+    max_line_length: usize,
+    discarding: bool,
+}
+
+impl<R: AsyncRead + Unpin> LineReader<R> {
+    /// Create a new line reader over the given stream
+    pub(crate) fn new(reader: R, max_line_length: usize) -> Self {
+        Self {
+            reader,
+            buffer: Vec::with_capacity(1024),
+            max_line_length,
+            discarding: false,
+        }
+    }
+
+    /// Get the maximum accepted line length
+    pub(crate) fn max_line_length(&self) -> usize {
+        self.max_line_length
+    }
+
+    /// Read the next frame. Returns Ok(None) once the stream is closed.
+    pub(crate) async fn next_frame(&mut self) -> std::io::Result<Option<Frame>> {
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+
+                if self.discarding {
+                    // Tail of an oversized line, already reported
+                    self.discarding = false;
+                    continue;
+                }
+
+                if pos > self.max_line_length {
+                    return Ok(Some(Frame::TooLong));
+                }
+
+                return Ok(Some(Frame::Line(Self::decode(&line[..pos]))));
+            }
+
+            if self.buffer.len() > self.max_line_length {
+                self.buffer.clear();
+                if !self.discarding {
+                    self.discarding = true;
+                    return Ok(Some(Frame::TooLong));
+                }
+            }
+
+            self.buffer.reserve(1024);
+            if self.reader.read_buf(&mut self.buffer).await? == 0 {
+                // Connection closed: flush an unterminated last line, if any
+                if self.buffer.is_empty() || self.discarding {
+                    return Ok(None);
+                }
+                let line = std::mem::take(&mut self.buffer);
+                return Ok(Some(Frame::Line(Self::decode(&line))));
+            }
+        }
+    }
+
+    /// Decode a raw line, dropping the trailing carriage return and whitespace
+    fn decode(line: &[u8]) -> String {
+        String::from_utf8_lossy(line).trim_end().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_test::io::Builder;
+
+    /// Read every frame, as `Some(line)` or `None` for `TooLong`
+    async fn frames(reader: &mut LineReader<impl AsyncRead + Unpin>) -> Vec<Option<String>> {
+        let mut frames = Vec::new();
+        while let Some(frame) = reader.next_frame().await.unwrap() {
+            frames.push(match frame {
+                Frame::Line(line) => Some(line),
+                Frame::TooLong => None,
+            });
+        }
+        frames
+    }
+
+    fn line(text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    #[tokio::test]
+    async fn joins_a_line_split_across_reads() {
+        let stream = Builder::new()
+            .read(b"hel")
+            .read(b"lo wo")
+            .read(b"rld\n")
+            .build();
+        let mut reader = LineReader::new(stream, 64);
+        assert_eq!(frames(&mut reader).await, [line("hello world")]);
+    }
+
+    #[tokio::test]
+    async fn splits_two_lines_from_one_read() {
+        let stream = Builder::new().read(b"first\nsecond\n").build();
+        let mut reader = LineReader::new(stream, 64);
+        assert_eq!(frames(&mut reader).await, [line("first"), line("second")]);
+    }
+
+    #[tokio::test]
+    async fn reports_an_overlong_line_once_then_recovers() {
+        let stream = Builder::new()
+            .read(&[b'x'; 20])
+            .read(&[b'x'; 20])
+            .read(b"xx\nok\n")
+            .build();
+        let mut reader = LineReader::new(stream, 16);
+        assert_eq!(frames(&mut reader).await, [None, line("ok")]);
+    }
+
+    #[tokio::test]
+    async fn reports_an_overlong_line_that_arrives_whole() {
+        let stream = Builder::new().read(b"0123456789abcdefXYZ\nok\n").build();
+        let mut reader = LineReader::new(stream, 16);
+        assert_eq!(frames(&mut reader).await, [None, line("ok")]);
+    }
+
+    #[tokio::test]
+    async fn drops_a_trailing_carriage_return() {
+        let stream = Builder::new().read(b"hello\r").read(b"\nbye\r\n").build();
+        let mut reader = LineReader::new(stream, 64);
+        assert_eq!(frames(&mut reader).await, [line("hello"), line("bye")]);
+    }
+
+    #[tokio::test]
+    async fn flushes_an_unterminated_last_line() {
+        let stream = Builder::new().read(b"one\ntwo").build();
+        let mut reader = LineReader::new(stream, 64);
+        assert_eq!(frames(&mut reader).await, [line("one"), line("two")]);
+    }
+}
@@ -1,2 +1,7 @@
+pub(crate) mod duration;
 pub(crate) mod error;
+pub(crate) mod framing;
+pub(crate) mod rate_limit;
+pub(crate) mod repeat;
+pub(crate) mod sanitize;
 pub(crate) mod target;
@@ -0,0 +1,182 @@
+use std::time::{Duration, Instant};
+
+/// Token bucket holding up to `capacity` tokens, refilled continuously at
+/// `refill_per_sec` tokens per second
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a full bucket
+    pub(crate) fn new(capacity: u32, refill_per_sec: f64) -> Self {
+        Self {
+            capacity: capacity as f64,
+            refill_per_sec,
+            tokens: capacity as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take one token if available. Returns false when the bucket is empty.
+    pub(crate) fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Violations in a row, each within a window of the one before, shared by
+/// the flood and repeat checks to decide when a client has earned a mute
+#[derive(Debug, Default)]
+pub(crate) struct ViolationCounter {
+    count: u32,
+    last: Option<Instant>,
+}
+
+impl ViolationCounter {
+    /// Count a violation and return how many there have been in a row. One
+    /// more than `window` after the last starts over at 1, and reaching
+    /// `mute_after` resets the count for next time.
+    pub(crate) fn record(&mut self, window: Duration, mute_after: u32) -> u32 {
+        let now = Instant::now();
+        let recent = self
+            .last
+            .is_some_and(|last| now.duration_since(last) <= window);
+        self.count = if recent { self.count + 1 } else { 1 };
+        self.last = Some(now);
+
+        let count = self.count;
+        if count >= mute_after {
+            self.count = 0;
+        }
+        count
+    }
+}
+
+/// Which limit an input line counts against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FloodKind {
+    Message,
+    Command,
+}
+
+/// Outcome of checking an input line against the flood limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FloodVerdict {
+    /// Within the limits
+    Allowed,
+    /// Over the limit, first violation in the current window
+    Warned,
+    /// Over the limit again, the line is dropped
+    Throttled,
+    /// Over the limit too many times, the client should be muted
+    Mute,
+}
+
+/// Per-client flood tracking: one bucket per kind of input and a count of
+/// recent violations
+#[derive(Debug, Default)]
+pub(crate) struct FloodState {
+    messages: Option<TokenBucket>,
+    commands: Option<TokenBucket>,
+    violations: ViolationCounter,
+}
+
+impl FloodState {
+    /// Count one line against the limits. `bucket` creates the bucket for
+    /// `kind` on first use. Violations older than `window` are forgotten, and
+    /// reaching `mute_after` violations resets the count and asks for a mute.
+    /// Commands over the limit are only throttled and never count as
+    /// violations, so they can't lead to a mute.
+    pub(crate) fn check(
+        &mut self,
+        kind: FloodKind,
+        bucket: impl FnOnce() -> TokenBucket,
+        window: Duration,
+        mute_after: u32,
+    ) -> FloodVerdict {
+        let slot = match kind {
+            FloodKind::Message => &mut self.messages,
+            FloodKind::Command => &mut self.commands,
+        };
+        if slot.get_or_insert_with(bucket).try_take() {
+            return FloodVerdict::Allowed;
+        }
+        if kind == FloodKind::Command {
+            return FloodVerdict::Throttled;
+        }
+
+        let violations = self.violations.record(window, mute_after);
+        if violations >= mute_after {
+            FloodVerdict::Mute
+        } else if violations == 1 {
+            FloodVerdict::Warned
+        } else {
+            FloodVerdict::Throttled
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn check(state: &mut FloodState, kind: FloodKind) -> FloodVerdict {
+        state.check(kind, || TokenBucket::new(1, 0.001), WINDOW, 3)
+    }
+
+    #[test]
+    fn message_violations_escalate_to_a_mute() {
+        let mut state = FloodState::default();
+        assert_eq!(check(&mut state, FloodKind::Message), FloodVerdict::Allowed);
+        assert_eq!(check(&mut state, FloodKind::Message), FloodVerdict::Warned);
+        assert_eq!(
+            check(&mut state, FloodKind::Message),
+            FloodVerdict::Throttled
+        );
+        assert_eq!(check(&mut state, FloodKind::Message), FloodVerdict::Mute);
+    }
+
+    #[test]
+    fn violations_start_over_after_a_mute_or_a_quiet_window() {
+        let mut counter = ViolationCounter::default();
+        assert_eq!(counter.record(WINDOW, 3), 1);
+        assert_eq!(counter.record(WINDOW, 3), 2);
+        assert_eq!(counter.record(WINDOW, 3), 3);
+        assert_eq!(counter.record(WINDOW, 3), 1);
+
+        // Once the window has passed, the next one is the first in a row
+        assert_eq!(counter.record(WINDOW, 3), 2);
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(counter.record(Duration::from_millis(1), 3), 1);
+    }
+
+    #[test]
+    fn command_floods_are_throttled_but_never_muted() {
+        let mut state = FloodState::default();
+        assert_eq!(check(&mut state, FloodKind::Command), FloodVerdict::Allowed);
+        for _ in 0..10 {
+            assert_eq!(
+                check(&mut state, FloodKind::Command),
+                FloodVerdict::Throttled
+            );
+        }
+        // Nor do they count towards a mute for messages
+        assert_eq!(check(&mut state, FloodKind::Message), FloodVerdict::Allowed);
+        assert_eq!(check(&mut state, FloodKind::Message), FloodVerdict::Warned);
+    }
+}
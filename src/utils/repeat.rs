@@ -0,0 +1,205 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use crate::utils::rate_limit::ViolationCounter;
+
+/// Upper bound on fingerprints remembered per client
+const MAX_TRACKED_PER_CLIENT: usize = 32;
+
+/// A message reduced to what makes it "the same" as another: case,
+/// punctuation, spacing and stretched letters ("hiiii") are ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Fingerprint {
+    hash: u64,
+    /// Length of the normalized text in characters
+    pub len: usize,
+}
+
+impl Fingerprint {
+    /// Fingerprint a message, or None when nothing is left after normalizing
+    pub(crate) fn of(message: &str) -> Option<Self> {
+        let mut normalized = String::with_capacity(message.len());
+        for c in message.chars().filter(|c| c.is_alphanumeric()) {
+            for c in c.to_lowercase() {
+                if !normalized.ends_with(c) {
+                    normalized.push(c);
+                }
+            }
+        }
+        if normalized.is_empty() {
+            return None;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        normalized.hash(&mut hasher);
+        Some(Self {
+            hash: hasher.finish(),
+            len: normalized.chars().count(),
+        })
+    }
+}
+
+/// Per-client repeat tracking: recent fingerprints and a count of recent
+/// violations
+#[derive(Debug, Default)]
+pub(crate) struct RepeatState {
+    recent: VecDeque<(Instant, Fingerprint)>,
+    violations: ViolationCounter,
+}
+
+impl RepeatState {
+    /// Record a message and return how many times the client sent it within
+    /// `window`, this one included
+    pub(crate) fn record(&mut self, fingerprint: Fingerprint, window: Duration) -> usize {
+        let now = Instant::now();
+        self.recent
+            .retain(|(sent_at, _)| now.duration_since(*sent_at) < window);
+        if self.recent.len() == MAX_TRACKED_PER_CLIENT {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((now, fingerprint));
+
+        self.recent
+            .iter()
+            .filter(|(_, recent)| *recent == fingerprint)
+            .count()
+    }
+
+    /// Count a blocked message. Violations older than `window` are forgotten,
+    /// and reaching `mute_after` violations resets the count and returns true.
+    pub(crate) fn violation(&mut self, window: Duration, mute_after: u32) -> bool {
+        self.violations.record(window, mute_after) >= mute_after
+    }
+}
+
+/// Server-wide record of recent messages, used to spot several clients
+/// posting the same text
+#[derive(Debug, Default)]
+pub(crate) struct RecentMessages {
+    /// Every recorded message in arrival order, for expiry
+    order: VecDeque<(Instant, Fingerprint)>,
+    /// Senders of each fingerprint, oldest first
+    senders: HashMap<Fingerprint, VecDeque<u32>>,
+}
+
+impl RecentMessages {
+    /// Record a message and return how many distinct clients posted it
+    /// within `window`, this one included
+    pub(crate) fn record(
+        &mut self,
+        fingerprint: Fingerprint,
+        sender_id: u32,
+        window: Duration,
+    ) -> usize {
+        let now = Instant::now();
+        while let Some(&(sent_at, expired)) = self.order.front() {
+            if now.duration_since(sent_at) < window {
+                break;
+            }
+            self.order.pop_front();
+            if let Some(senders) = self.senders.get_mut(&expired) {
+                senders.pop_front();
+                if senders.is_empty() {
+                    self.senders.remove(&expired);
+                }
+            }
+        }
+
+        self.order.push_back((now, fingerprint));
+        let senders = self.senders.entry(fingerprint).or_default();
+        senders.push_back(sender_id);
+
+        let mut distinct: Vec<u32> = senders.iter().copied().collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        distinct.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn fingerprint(message: &str) -> Fingerprint {
+        Fingerprint::of(message).unwrap()
+    }
+
+    #[test]
+    fn fingerprints_ignore_case_punctuation_and_stretching() {
+        let hello = fingerprint("hello world");
+        assert_eq!(fingerprint("HELLO, World!!!"), hello);
+        assert_eq!(fingerprint("  hello   world  "), hello);
+        assert_eq!(fingerprint("heeeellooo wooorld"), hello);
+        assert_eq!(hello.len, "heloworld".len());
+        assert_ne!(fingerprint("hello word"), hello);
+        assert_eq!(fingerprint("ÉCOLE"), fingerprint("école"));
+
+        assert_eq!(Fingerprint::of("?!... :)"), None);
+        assert_eq!(Fingerprint::of(""), None);
+    }
+
+    #[test]
+    fn record_counts_the_same_message_within_the_window() {
+        let mut state = RepeatState::default();
+        assert_eq!(state.record(fingerprint("buy now"), WINDOW), 1);
+        assert_eq!(state.record(fingerprint("something else"), WINDOW), 1);
+        assert_eq!(state.record(fingerprint("BUY NOW!"), WINDOW), 2);
+        assert_eq!(state.record(fingerprint("buuuy now"), WINDOW), 3);
+
+        // Older messages fall out of a short window
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(
+            state.record(fingerprint("buy now"), Duration::from_millis(1)),
+            1
+        );
+    }
+
+    #[test]
+    fn record_remembers_a_bounded_number_of_messages() {
+        let mut state = RepeatState::default();
+        let spam = fingerprint("spam");
+        for _ in 0..MAX_TRACKED_PER_CLIENT {
+            state.record(spam, WINDOW);
+        }
+        assert_eq!(state.record(spam, WINDOW), MAX_TRACKED_PER_CLIENT);
+    }
+
+    #[test]
+    fn violations_reach_a_mute_and_start_over() {
+        let mut state = RepeatState::default();
+        assert!(!state.violation(WINDOW, 2));
+        assert!(state.violation(WINDOW, 2));
+        assert!(!state.violation(WINDOW, 2));
+    }
+
+    #[test]
+    fn raids_count_distinct_senders() {
+        let mut recent = RecentMessages::default();
+        let raid = fingerprint("join my server");
+        assert_eq!(recent.record(raid, 1, WINDOW), 1);
+        // The same sender again is still one sender
+        assert_eq!(recent.record(raid, 1, WINDOW), 1);
+        assert_eq!(recent.record(fingerprint("hi all"), 2, WINDOW), 1);
+        assert_eq!(recent.record(fingerprint("JOIN MY SERVER!"), 2, WINDOW), 2);
+        assert_eq!(recent.record(raid, 3, WINDOW), 3);
+    }
+
+    #[test]
+    fn raid_senders_expire_with_the_window() {
+        let mut recent = RecentMessages::default();
+        let raid = fingerprint("join my server");
+        let short = Duration::from_millis(1);
+        recent.record(raid, 1, short);
+        recent.record(raid, 2, short);
+        std::thread::sleep(Duration::from_millis(2));
+
+        assert_eq!(recent.record(raid, 3, short), 1);
+        // Expired fingerprints are dropped entirely
+        assert_eq!(recent.senders.len(), 1);
+        assert_eq!(recent.order.len(), 1);
+    }
+}
@@ -0,0 +1,231 @@
+use std::borrow::Cow;
+
+use crate::config::SanitizeMode;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+/// Single-character C1 forms of CSI and the string terminator
+const C1_CSI: char = '\u{9b}';
+const C1_ST: char = '\u{9c}';
+/// Appended after an unterminated color so it never bleeds into later lines
+const SGR_RESET: &str = "\x1b[0m";
+
+/// Make a line safe to print on other users' terminals. Control characters
+/// other than tab are removed or escaped along with the whole escape
+/// sequence they start (CSI, OSC, DCS and friends), so a sender cannot clear
+/// screens, move cursors or retitle windows. With `allow_colors`, plain SGR
+/// color and style sequences are kept and the line ends with a reset.
+pub(crate) fn sanitize(input: &str, mode: SanitizeMode, allow_colors: bool) -> Cow<'_, str> {
+    if !input.chars().any(is_unsafe) {
+        return Cow::Borrowed(input);
+    }
+
+    let mut output = String::with_capacity(input.len());
+    let mut color_open = false;
+    let mut rest = input;
+
+    while let Some(start) = rest.find(is_unsafe) {
+        output.push_str(&rest[..start]);
+        let sequence = &rest[start..start + sequence_len(&rest[start..])];
+        rest = &rest[start + sequence.len()..];
+
+        if allow_colors && is_safe_sgr(sequence) {
+            output.push_str(sequence);
+            color_open = !matches!(sequence, "\x1b[m" | "\x1b[0m");
+            continue;
+        }
+        if mode == SanitizeMode::Escape {
+            for c in sequence.chars() {
+                if is_unsafe(c) {
+                    output.push_str(&format!("\\x{:02x}", c as u32));
+                } else {
+                    output.push(c);
+                }
+            }
+        }
+    }
+    output.push_str(rest);
+
+    if color_open {
+        output.push_str(SGR_RESET);
+    }
+    Cow::Owned(output)
+}
+
+/// C0 controls except tab, DEL and C1 controls
+fn is_unsafe(c: char) -> bool {
+    c.is_control() && c != '\t'
+}
+
+/// Length in bytes of the escape sequence or control character at the
+/// start of `input`
+fn sequence_len(input: &str) -> usize {
+    let mut chars = input.chars();
+    let Some(first) = chars.next() else {
+        return 0;
+    };
+    let first_len = first.len_utf8();
+    let body = &input[first_len..];
+
+    match first {
+        ESC => match chars.next() {
+            Some('[') => first_len + 1 + csi_len(&body[1..]),
+            Some(']' | 'P' | 'X' | '^' | '_') => first_len + 1 + string_len(&body[1..]),
+            Some(next) if ('\x20'..='\x7e').contains(&next) => first_len + escape_len(body),
+            _ => first_len,
+        },
+        C1_CSI => first_len + csi_len(body),
+        // OSC, DCS, SOS, PM and APC carry a string up to a terminator
+        '\u{9d}' | '\u{90}' | '\u{98}' | '\u{9e}' | '\u{9f}' => first_len + string_len(body),
+        _ => first_len,
+    }
+}
+
+/// Length of a CSI sequence's parameters and final byte
+fn csi_len(body: &str) -> usize {
+    for (i, c) in body.char_indices() {
+        if ('\x40'..='\x7e').contains(&c) {
+            return i + 1;
+        }
+        if !('\x20'..='\x3f').contains(&c) {
+            // Malformed, the offending character is handled on its own
+            return i;
+        }
+    }
+    body.len()
+}
+
+/// Length of a string sequence (e.g. OSC) including its BEL or ST terminator.
+/// An unterminated sequence runs to the end of the line.
+fn string_len(body: &str) -> usize {
+    let mut chars = body.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            BEL | C1_ST => return i + c.len_utf8(),
+            ESC if chars.peek().is_some_and(|(_, next)| *next == '\\') => return i + 2,
+            _ => {}
+        }
+    }
+    body.len()
+}
+
+/// Length of a two-character escape, or one with intermediate bytes like `ESC ( B`
+fn escape_len(body: &str) -> usize {
+    for (i, c) in body.char_indices() {
+        if !('\x20'..='\x2f').contains(&c) {
+            return if ('\x30'..='\x7e').contains(&c) {
+                i + 1
+            } else {
+                i
+            };
+        }
+    }
+    body.len()
+}
+
+/// Check for an SGR sequence (`ESC [ ... m`) that only sets colors and
+/// basic text styles
+fn is_safe_sgr(sequence: &str) -> bool {
+    let Some(params) = sequence
+        .strip_prefix("\x1b[")
+        .and_then(|sequence| sequence.strip_suffix('m'))
+    else {
+        return false;
+    };
+
+    let mut params = params.split(';').map(|param| {
+        if param.is_empty() {
+            Some(0)
+        } else {
+            param.parse::<u16>().ok()
+        }
+    });
+
+    while let Some(param) = params.next() {
+        match param {
+            Some(0..=4 | 22..=24 | 30..=37 | 39..=47 | 49 | 90..=97 | 100..=107) => {}
+            // Extended colors: 38;5;n, 38;2;r;g;b and their background forms
+            Some(38 | 48) => {
+                let components = match params.next() {
+                    Some(Some(5)) => 1,
+                    Some(Some(2)) => 3,
+                    _ => return false,
+                };
+                for _ in 0..components {
+                    if !matches!(params.next(), Some(Some(0..=255))) {
+                        return false;
+                    }
+                }
+            }
+            _ => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use SanitizeMode::{Escape, Strip};
+
+    #[test]
+    fn sanitizes_control_sequences() {
+        let cases: &[(&str, SanitizeMode, bool, &str)] = &[
+            // Clipboard write (OSC 52), terminated by BEL or ST
+            ("copy \x1b]52;c;aGVsbG8=\x07 me", Strip, false, "copy  me"),
+            ("\x1b]52;c;aGk=\x1b\\done", Strip, false, "done"),
+            // Screen clear and cursor moves
+            ("a\x1b[2Jb\x1b[10;5Hc", Strip, false, "abc"),
+            // A bare ESC at the end of input
+            ("end\x1b", Strip, false, "end"),
+            // Single-character C1 CSI
+            ("x\u{9b}31my", Strip, false, "xy"),
+            // Other C0 controls go, tabs stay
+            ("ding\x07\tdong", Strip, false, "ding\tdong"),
+            // Colors are stripped unless allowed
+            ("\x1b[31mred", Strip, false, "red"),
+            // Allowed SGR is kept and an open color is reset at the end
+            ("\x1b[31mred", Strip, true, "\x1b[31mred\x1b[0m"),
+            (
+                "\x1b[38;5;200mpink",
+                Strip,
+                true,
+                "\x1b[38;5;200mpink\x1b[0m",
+            ),
+            (
+                "\x1b[1;31mred\x1b[0m plain",
+                Strip,
+                true,
+                "\x1b[1;31mred\x1b[0m plain",
+            ),
+            ("\x1b[31m\x1b[2J", Strip, true, "\x1b[31m\x1b[0m"),
+            // SGR outside the allowed set, e.g. blink, is still stripped
+            ("\x1b[5mblink", Strip, true, "blink"),
+            // Escape mode shows whole sequences as visible text
+            ("a\x1b[2Jb", Escape, false, "a\\x1b[2Jb"),
+            ("t\x1b]0;title\x07", Escape, false, "t\\x1b]0;title\\x07"),
+            ("end\x1b", Escape, false, "end\\x1b"),
+            ("x\u{9b}31my", Escape, false, "x\\x9b31my"),
+            ("\x1b[31mred", Escape, true, "\x1b[31mred\x1b[0m"),
+        ];
+
+        for (input, mode, allow_colors, expected) in cases {
+            assert_eq!(
+                sanitize(input, *mode, *allow_colors),
+                *expected,
+                "sanitizing {:?} ({:?}, colors {})",
+                input,
+                mode,
+                allow_colors
+            );
+        }
+    }
+
+    #[test]
+    fn borrows_safe_input() {
+        assert!(matches!(
+            sanitize("plain\ttext", Strip, false),
+            Cow::Borrowed("plain\ttext")
+        ));
+    }
+}
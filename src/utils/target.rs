@@ -1,6 +1,9 @@
-use crate::shared_state::ClientMap;
+use std::sync::Arc;
+
+use crate::outbox::Outbox;
+use crate::server_message::{ErrorCode, OutboundMessage, ServerMessage};
+use crate::shared_state::{ClientControl, ClientMap};
 use crate::utils::error::BoxError;
-use tokio::sync::mpsc;
 
 /// Raw target identifier - can be either an ID or a nickname
 #[derive(Debug, Clone)]
@@ -13,6 +16,7 @@ pub(crate) enum Target {
 
 /// Target that must be an ID
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub(crate) struct TargetId(pub String);
 
 /// Target that must be a nickname
@@ -47,6 +51,7 @@ impl Target {
 
 impl TargetId {
     /// Create a TargetId from command arguments
+    #[allow(dead_code)]
     pub(crate) fn from_args(args: &str) -> Option<Self> {
         let trimmed = args.trim();
         if trimmed.is_empty() {
@@ -74,27 +79,34 @@ impl ValidatedTarget {
     /// Validate a Target (can be either ID or Name)
     pub(crate) async fn from_target(
         target: &Target,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         clients: &ClientMap,
     ) -> Result<Self, BoxError> {
         match target {
             Target::Id(id_str) => Self::from_id(id_str, tx, clients).await,
             Target::Name(name) => Self::from_name(name, tx, clients).await,
             Target::Both(input) => {
-                // Try as ID first, then as name
-                if let Ok(target) = Self::from_id(input, tx, clients).await {
-                    Ok(target)
-                } else {
-                    Self::from_name(input, tx, clients).await
+                // Try as ID first without reporting errors, then as name
+                if let Ok(user_id) = input.parse::<u32>() {
+                    if let Some(nickname) =
+                        clients.get(user_id, |client_state| client_state.nickname().to_string())
+                    {
+                        return Ok(ValidatedTarget {
+                            id: user_id,
+                            nickname,
+                        });
+                    }
                 }
+                Self::from_name(input, tx, clients).await
             }
         }
     }
 
     /// Validate a TargetId (must be a numeric ID)
+    #[allow(dead_code)]
     pub(crate) async fn from_target_id(
         target_id: &TargetId,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         clients: &ClientMap,
     ) -> Result<Self, BoxError> {
         Self::from_id(&target_id.0, tx, clients).await
@@ -104,36 +116,38 @@ impl ValidatedTarget {
     #[allow(dead_code)]
     pub(crate) async fn from_target_name(
         target_name: &TargetName,
-        tx: &mpsc::Sender<String>,
+        tx: &Outbox,
         clients: &ClientMap,
     ) -> Result<Self, BoxError> {
         Self::from_name(&target_name.0, tx, clients).await
     }
 
     /// Internal: Validate by ID
-    async fn from_id(
-        id_str: &str,
-        tx: &mpsc::Sender<String>,
-        clients: &ClientMap,
-    ) -> Result<Self, BoxError> {
+    async fn from_id(id_str: &str, tx: &Outbox, clients: &ClientMap) -> Result<Self, BoxError> {
         // Parse as u32
         let user_id: u32 = match id_str.parse() {
             Ok(id) => id,
             Err(_) => {
-                tx.send(format!("Error: Invalid user ID: {}\n", id_str))
-                    .await?;
+                tx.send(ServerMessage::error(
+                    ErrorCode::InvalidArgument,
+                    format!("Invalid user ID: {}", id_str),
+                ))
+                .await?;
                 return Err("Invalid user ID".into());
             }
         };
 
         // Lookup in clients
-        let clients_lock = clients.lock().await;
-        let nickname = if let Some(client_state) = clients_lock.get(&user_id) {
-            client_state.nickname.clone()
+        let nickname = if let Some(nickname) =
+            clients.get(user_id, |client_state| client_state.nickname().to_string())
+        {
+            nickname
         } else {
-            drop(clients_lock);
-            tx.send(format!("Error: User with ID {} not found\n", user_id))
-                .await?;
+            tx.send(ServerMessage::error(
+                ErrorCode::NotFound,
+                format!("User with ID {} not found", user_id),
+            ))
+            .await?;
             return Err("User not found".into());
         };
 
@@ -144,26 +158,22 @@ impl ValidatedTarget {
     }
 
     /// Internal: Validate by nickname
-    async fn from_name(
-        name: &str,
-        tx: &mpsc::Sender<String>,
-        clients: &ClientMap,
-    ) -> Result<Self, BoxError> {
-        let clients_lock = clients.lock().await;
-
-        // Search for user by nickname
-        for (user_id, client_state) in clients_lock.iter() {
-            if client_state.nickname.eq_ignore_ascii_case(name) {
-                return Ok(ValidatedTarget {
-                    id: *user_id,
-                    nickname: client_state.nickname.clone(),
-                });
-            }
+    async fn from_name(name: &str, tx: &Outbox, clients: &ClientMap) -> Result<Self, BoxError> {
+        // Look the user up in the nickname index
+        let found = clients.find_by_nickname(name).and_then(|user_id| {
+            clients
+                .get(user_id, |client_state| client_state.nickname().to_string())
+                .map(|nickname| (user_id, nickname))
+        });
+        if let Some((id, nickname)) = found {
+            return Ok(ValidatedTarget { id, nickname });
         }
 
-        drop(clients_lock);
-        tx.send(format!("Error: User '{}' not found\n", name))
-            .await?;
+        tx.send(ServerMessage::error(
+            ErrorCode::NotFound,
+            format!("User '{}' not found", name),
+        ))
+        .await?;
         Err("User not found".into())
     }
 
@@ -177,28 +187,74 @@ impl ValidatedTarget {
         &self.nickname
     }
 
+    /// Check that the caller outranks this target, so moderators cannot
+    /// act on each other or on an admin. Refuses with `PermissionDenied` and
+    /// returns false otherwise.
+    pub(crate) async fn outranked_by(
+        &self,
+        clients: &ClientMap,
+        caller_id: u32,
+        tx: &Outbox,
+    ) -> Result<bool, BoxError> {
+        let caller_role = clients
+            .get(caller_id, |client_state| client_state.role())
+            .unwrap_or_default();
+        let target_role = clients
+            .get(self.id, |client_state| client_state.role())
+            .unwrap_or_default();
+        if target_role < caller_role {
+            return Ok(true);
+        }
+
+        tx.send(ServerMessage::refused(
+            ErrorCode::PermissionDenied,
+            format!(
+                "Permission denied: {} has the {} role",
+                self.nickname, target_role
+            ),
+        ))
+        .await?;
+        Ok(false)
+    }
+
     /// Send a direct message to this target user
     pub(crate) async fn send_message(
         &self,
         clients: &ClientMap,
-        message: &str,
+        message: ServerMessage,
+    ) -> Result<(), BoxError> {
+        let tx = clients.get(self.id, |client_state| client_state.tx.clone());
+        if let Some(tx) = tx {
+            tx.send(message).await?;
+        }
+        Ok(())
+    }
+
+    /// Send a control signal to this target user's connection task
+    pub(crate) async fn send_control(
+        &self,
+        clients: &ClientMap,
+        control: ClientControl,
     ) -> Result<(), BoxError> {
-        let clients_lock = clients.lock().await;
-        if let Some(client_state) = clients_lock.get(&self.id) {
-            client_state.tx.send(message.to_string()).await?;
+        let control_tx = clients.get(self.id, |client_state| client_state.control.clone());
+        if let Some(control_tx) = control_tx {
+            control_tx
+                .send(control)
+                .await
+                .map_err(|_| "Client is no longer connected")?;
         }
         Ok(())
     }
 
-    /// Broadcast a message to all clients
+    /// Broadcast a server announcement to all clients
     pub(crate) async fn broadcast_to_all(
         clients: &ClientMap,
         message: &str,
     ) -> Result<(), BoxError> {
-        let clients_lock = clients.lock().await;
-        for (_, client_state) in clients_lock.iter() {
-            let _ = client_state.tx.send(message.to_string()).await;
-        }
+        let message = Arc::new(OutboundMessage::new(ServerMessage::system(message)));
+        clients.for_each(|_, client_state| {
+            let _ = client_state.tx.push(Arc::clone(&message));
+        });
         Ok(())
     }
 }
@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A blocked term and how it is matched
+#[derive(Debug, Clone)]
+struct Term {
+    /// Case-folded characters of the term
+    chars: Vec<char>,
+    /// Match anywhere, not only as a whole word
+    substring: bool,
+}
+
+impl Term {
+    /// Parse a line from the word list: `word` matches whole words only,
+    /// `*word*` matches anywhere
+    fn parse(line: &str) -> Option<Self> {
+        let (text, substring) = match line
+            .strip_prefix('*')
+            .and_then(|rest| rest.strip_suffix('*'))
+        {
+            Some(inner) => (inner.trim(), true),
+            None => (line, false),
+        };
+        if text.is_empty() {
+            return None;
+        }
+        Some(Self {
+            chars: text.chars().map(fold).collect(),
+            substring,
+        })
+    }
+}
+
+/// Blocked terms loaded from a file, with optional per-channel additions.
+///
+/// File format: one term per line, `#` starts a comment. Terms before any
+/// section apply everywhere; terms under a `[#channel]` header only apply in
+/// that channel. `*term*` matches inside words, otherwise only whole words.
+#[derive(Debug)]
+pub(crate) struct WordList {
+    path: PathBuf,
+    global: Vec<Term>,
+    channels: HashMap<String, Vec<Term>>,
+}
+
+impl WordList {
+    /// Load the list from `path`. A missing file yields an empty list.
+    pub(crate) fn load(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut global = Vec::new();
+        let mut channels: HashMap<String, Vec<Term>> = HashMap::new();
+        let mut section: Option<String> = None;
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(header) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                section = Some(header.trim().to_lowercase()).filter(|name| name != "*");
+                continue;
+            }
+            let Some(term) = Term::parse(line) else {
+                eprintln!(
+                    "Ignoring malformed word list entry at {}:{}",
+                    path.display(),
+                    index + 1
+                );
+                continue;
+            };
+            match &section {
+                Some(channel) => channels.entry(channel.clone()).or_default().push(term),
+                None => global.push(term),
+            }
+        }
+
+        let list = Self {
+            path,
+            global,
+            channels,
+        };
+        println!(
+            "Loaded {} filtered term(s) from {}",
+            list.len(),
+            list.path.display()
+        );
+        Ok(list)
+    }
+
+    /// Load the list from `path` on the blocking thread pool, so a reload
+    /// neither stalls the runtime nor holds the shared list while reading
+    pub(crate) async fn load_in_background(path: PathBuf) -> io::Result<Self> {
+        tokio::task::spawn_blocking(move || Self::load(path))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// The file this list was loaded from
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Total number of terms, global and per channel
+    pub(crate) fn len(&self) -> usize {
+        self.global.len() + self.channels.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Check if a message contains a term that applies in `channel`
+    /// (None for private messages, where only global terms apply)
+    pub(crate) fn contains_match(&self, channel: Option<&str>, message: &str) -> bool {
+        let folded: Vec<char> = message.chars().map(fold).collect();
+        self.terms(channel)
+            .any(|term| find_matches(&folded, term).next().is_some())
+    }
+
+    /// Replace every matching term in a message with asterisks.
+    /// Returns None when nothing matched.
+    pub(crate) fn mask(&self, channel: Option<&str>, message: &str) -> Option<String> {
+        let mut chars: Vec<char> = message.chars().collect();
+        let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+
+        let mut masked = false;
+        for term in self.terms(channel) {
+            for start in find_matches(&folded, term) {
+                chars[start..start + term.chars.len()].fill('*');
+                masked = true;
+            }
+        }
+        masked.then(|| chars.into_iter().collect())
+    }
+
+    /// Terms that apply in a channel
+    fn terms<'a>(&'a self, channel: Option<&str>) -> impl Iterator<Item = &'a Term> {
+        let channel_terms = channel
+            .and_then(|channel| self.channels.get(channel))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        self.global.iter().chain(channel_terms)
+    }
+}
+
+/// Fold a character for case-insensitive matching, keeping one char per char
+/// so positions in the folded text line up with the original
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Check if a character can be part of a word
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Start positions where a term matches in folded text
+fn find_matches<'a>(folded: &'a [char], term: &'a Term) -> impl Iterator<Item = usize> + 'a {
+    let len = term.chars.len();
+    (0..=folded.len().saturating_sub(len))
+        .filter(move |&start| folded.len() >= len && folded[start..start + len] == term.chars[..])
+        .filter(move |&start| {
+            term.substring
+                || ((start == 0 || !is_word_char(folded[start - 1]))
+                    && folded.get(start + len).is_none_or(|&c| !is_word_char(c)))
+        })
+}
+
+pub(crate) type SharedWordList = Arc<tokio::sync::Mutex<WordList>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load a list from `contents`, through a file named after `name`
+    fn list(name: &str, contents: &str) -> WordList {
+        let path = std::env::temp_dir().join(format!(
+            "word-list-test-{}-{}.txt",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        let list = WordList::load(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        list
+    }
+
+    #[test]
+    fn plain_terms_match_whole_words_only() {
+        let list = list("whole", "heck\n");
+        assert!(list.contains_match(None, "oh heck!"));
+        assert!(list.contains_match(None, "heck"));
+        assert!(list.contains_match(None, "(heck)"));
+        assert!(!list.contains_match(None, "checking"));
+        assert!(!list.contains_match(None, "heck_yes"));
+    }
+
+    #[test]
+    fn starred_terms_match_inside_words() {
+        let list = list("substring", "*heck*\n");
+        assert!(list.contains_match(None, "checking"));
+        assert!(list.contains_match(None, "heck"));
+        assert!(!list.contains_match(None, "hec k"));
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        let list = list("case", "Darn\n*ÉCLAIR*\n");
+        assert!(list.contains_match(None, "DARN it"));
+        assert!(list.contains_match(None, "darn"));
+        assert!(list.contains_match(None, "two éclairs"));
+    }
+
+    #[test]
+    fn channel_sections_only_apply_in_their_channel() {
+        let list = list(
+            "sections",
+            "# everywhere\nglobal\n\n[#Kids]\nspoiler\n[*]\nalso_global\n",
+        );
+        assert_eq!(list.len(), 3);
+        assert!(list.contains_match(None, "global"));
+        assert!(list.contains_match(Some("#general"), "also_global"));
+        assert!(list.contains_match(Some("#kids"), "a spoiler"));
+        assert!(!list.contains_match(Some("#general"), "a spoiler"));
+        // Private messages only get the global terms
+        assert!(!list.contains_match(None, "a spoiler"));
+    }
+
+    #[test]
+    fn mask_replaces_each_match_and_keeps_the_rest() {
+        let list = list("mask", "heck\n*darn*\n");
+        assert_eq!(
+            list.mask(None, "Heck, DARNED checks").as_deref(),
+            Some("****, ****ED checks")
+        );
+        assert_eq!(list.mask(None, "é heck é").as_deref(), Some("é **** é"));
+        assert_eq!(list.mask(None, "all clear"), None);
+    }
+
+    #[test]
+    fn missing_file_is_an_empty_list() {
+        let list = WordList::load("/nonexistent/words.txt").unwrap();
+        assert_eq!(list.len(), 0);
+        assert!(!list.contains_match(None, "anything"));
+    }
+}